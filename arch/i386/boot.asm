@@ -6,7 +6,10 @@ section .text
 bits 32
 start:
 	mov esp, stack_top
+	xor ebp, ebp					; end of the frame pointer chain
 	extern kmain
+	push ebx						; address of the boot information
+	push eax						; multiboot2 magic value
 	call kmain
 
 section .bss
@@ -0,0 +1,25 @@
+use std::env;
+
+/// The callers recorded by alloc_trace and lock_debug are found by walking
+/// the frame pointers, tell the kernel if the codegen keeps them.
+fn main() {
+    println!("cargo:rerun-if-env-changed=CARGO_ENCODED_RUSTFLAGS");
+    println!("cargo:rustc-check-cfg=cfg(frame_pointers)");
+    let rustflags = env::var("CARGO_ENCODED_RUSTFLAGS").unwrap_or_default();
+    let frame_pointers = rustflags
+        .split('\x1f')
+        .filter_map(|flag| flag.trim_start_matches("-C").split_once('='))
+        .rev()
+        .find(|(name, _)| *name == "force-frame-pointers")
+        .is_some_and(|(_, value)| ["yes", "y", "on", "true", "always"].contains(&value));
+    if frame_pointers {
+        println!("cargo:rustc-cfg=frame_pointers");
+    } else if env::var_os("CARGO_FEATURE_ALLOC_TRACE").is_some()
+        || env::var_os("CARGO_FEATURE_LOCK_DEBUG").is_some()
+    {
+        println!(
+            "cargo:warning=built without frame pointers, the traced callers are \
+             unknown (set RUSTFLAGS=\"-C force-frame-pointers=yes\")"
+        );
+    }
+}
@@ -0,0 +1,214 @@
+use core::str::from_utf8;
+
+use super::{identify_string, Bus, Error, STATUS_DRQ, TIMEOUT, WORDS_PER_SECTOR};
+use crate::{
+    block::{self, BlockDevice},
+    spinlock::Spinlock,
+};
+
+/// Signature left in lba_mid and lba_high by a packet device.
+pub const SIGNATURE: (u8, u8) = (0x14, 0xeb);
+
+/// Size of the sectors of a CD.
+pub const CD_SECTOR_SIZE: usize = 2048;
+
+const CMD_PACKET: u8 = 0xa0;
+const CMD_IDENTIFY_PACKET: u8 = 0xa1;
+
+// SCSI operation codes
+const TEST_UNIT_READY: u8 = 0x00;
+const READ_CAPACITY: u8 = 0x25;
+const READ_10: u8 = 0x28;
+
+// Sense keys, the high nibble of the error register
+pub const SENSE_NOT_READY: u8 = 0x2;
+pub const SENSE_UNIT_ATTENTION: u8 = 0x6;
+
+/// Largest byte count asked for each data transfer.
+const BYTE_COUNT_MAX: usize = 0xf800;
+
+/// Sectors read by one READ(10), under the byte count limit.
+const READ_MAX_SECTORS: usize = BYTE_COUNT_MAX / CD_SECTOR_SIZE;
+
+/// TEST UNIT READY sent at most while the drive reports a unit attention.
+const ATTENTION_RETRIES: usize = 4;
+
+impl Bus {
+    /// Send IDENTIFY PACKET DEVICE and return the 256 words it answers.
+    pub fn identify_packet(&mut self, slave: bool) -> Result<[u16; WORDS_PER_SECTOR], Error> {
+        self.select(slave, 0xa0)?;
+        unsafe { self.command.write(CMD_IDENTIFY_PACKET) };
+        self.delay();
+        self.wait_data()?;
+        let mut words = [0; WORDS_PER_SECTOR];
+        unsafe { self.data.read_string(&mut words) };
+        Ok(words)
+    }
+
+    /// Send a SCSI command packet with PIO and read its data in buf,
+    /// return the number of bytes read.
+    pub fn packet(
+        &mut self,
+        slave: bool,
+        packet: &[u8; 12],
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        self.select(slave, 0xa0)?;
+        let limit = buf.len().clamp(2, BYTE_COUNT_MAX) & !1;
+        unsafe {
+            self.features.write(0);
+            self.lba_mid.write(limit as u8);
+            self.lba_high.write((limit >> 8) as u8);
+            self.command.write(CMD_PACKET);
+        }
+        self.delay();
+        self.wait_data()?;
+        let mut words = [0u16; 6];
+        for (word, bytes) in words.iter_mut().zip(packet.chunks(2)) {
+            *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+        }
+        unsafe { self.data.write_string(&words) };
+        self.delay();
+        let mut done = 0;
+        for _ in 0..TIMEOUT {
+            let status = self.wait_not_busy()?;
+            self.check_packet_status(status)?;
+            if status & STATUS_DRQ == 0 {
+                return Ok(done);
+            }
+            let count =
+                unsafe { self.lba_mid.read() as usize | (self.lba_high.read() as usize) << 8 };
+            for _ in 0..count.div_ceil(2) {
+                let bytes = unsafe { self.data.read() }.to_le_bytes();
+                // What doesn't fit in buf is dropped.
+                for byte in bytes.iter() {
+                    if done < buf.len() {
+                        buf[done] = *byte;
+                        done += 1;
+                    }
+                }
+            }
+            self.delay();
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Like check_status, but the error register holds a sense key.
+    fn check_packet_status(&mut self, status: u8) -> Result<(), Error> {
+        match self.check_status(status) {
+            Err(Error::Command(err)) if err >> 4 != 0 => Err(Error::Sense(err >> 4)),
+            result => result,
+        }
+    }
+}
+
+/// An ATAPI CD-ROM drive on one of the IDE channels, read-only.
+pub struct AtapiDrive {
+    name: &'static str,
+    bus: &'static Spinlock<Bus>,
+    slave: bool,
+    model: [u8; 40],
+    /// Sectors of the medium, 0 without one.
+    sectors: Spinlock<u64>,
+}
+
+impl AtapiDrive {
+    pub(super) fn new(
+        name: &'static str,
+        bus: &'static Spinlock<Bus>,
+        slave: bool,
+        words: &[u16; WORDS_PER_SECTOR],
+    ) -> Self {
+        let mut model = [0; 40];
+        identify_string(&words[27..47], &mut model);
+        Self {
+            name,
+            bus,
+            slave,
+            model,
+            sectors: Spinlock::with_name(0, "ATAPI SECTORS"),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn model(&self) -> &str {
+        from_utf8(&self.model).unwrap_or("?").trim()
+    }
+
+    fn packet(&self, packet: &[u8; 12], buf: &mut [u8]) -> Result<usize, Error> {
+        self.bus.lock_irq().packet(self.slave, packet, buf)
+    }
+
+    /// Wait until the drive is ready, clearing the unit attentions of a
+    /// reset or of a medium change, and read the capacity of the medium.
+    pub fn refresh(&self) -> Result<u64, Error> {
+        let mut result = Err(Error::Sense(SENSE_UNIT_ATTENTION));
+        for _ in 0..ATTENTION_RETRIES {
+            result = self.packet(&[TEST_UNIT_READY, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], &mut []);
+            if result != Err(Error::Sense(SENSE_UNIT_ATTENTION)) {
+                break;
+            }
+        }
+        let sectors = result.and_then(|_| {
+            let mut data = [0; 8];
+            let packet = [READ_CAPACITY, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+            if self.packet(&packet, &mut data)? < data.len() {
+                return Err(Error::DeviceFault);
+            }
+            let last = u32::from_be_bytes([data[0], data[1], data[2], data[3]]);
+            Ok(last as u64 + 1)
+        });
+        *self.sectors.lock() = *sectors.as_ref().unwrap_or(&0);
+        sectors
+    }
+}
+
+impl BlockDevice for AtapiDrive {
+    fn sector_size(&self) -> usize {
+        CD_SECTOR_SIZE
+    }
+
+    fn sector_count(&self) -> u64 {
+        *self.sectors.lock()
+    }
+
+    /// A medium inserted since the last access is found by a read past
+    /// the end of the previous one, or of none. A medium changed under a
+    /// mounted filesystem fails the read that notices it.
+    fn read_sectors(&self, lba: u64, buf: &mut [u8]) -> Result<(), block::Error> {
+        if self.check_range(lba, buf.len()) == Err(block::Error::OutOfRange) {
+            self.refresh()?;
+        }
+        self.check_range(lba, buf.len())?;
+        let mut lba = lba;
+        for part in buf.chunks_mut(READ_MAX_SECTORS * CD_SECTOR_SIZE) {
+            let sectors = (part.len() / CD_SECTOR_SIZE) as u16;
+            let address = (lba as u32).to_be_bytes();
+            let length = sectors.to_be_bytes();
+            let packet = [
+                READ_10, 0, address[0], address[1], address[2], address[3], 0, length[0],
+                length[1], 0, 0, 0,
+            ];
+            match self.packet(&packet, part) {
+                Ok(len) if len == part.len() => {}
+                Ok(_) => return Err(Error::DeviceFault.into()),
+                Err(e @ Error::Sense(SENSE_UNIT_ATTENTION))
+                | Err(e @ Error::Sense(SENSE_NOT_READY)) => {
+                    // The capacity is of a medium which is gone.
+                    let _ = self.refresh();
+                    return Err(e.into());
+                }
+                Err(e) => return Err(e.into()),
+            }
+            lba += sectors as u64;
+        }
+        Ok(())
+    }
+
+    fn write_sectors(&self, _lba: u64, _buf: &[u8]) -> Result<(), block::Error> {
+        Err(block::Error::ReadOnly)
+    }
+}
@@ -0,0 +1,474 @@
+use alloc::{sync::Arc, vec::Vec};
+use core::{fmt, str::from_utf8};
+
+use crate::{
+    block::{self, BlockDevice, SECTOR_SIZE},
+    once::Once,
+    port::{Port, PortReadOnly, PortWriteOnly},
+    spinlock::Spinlock,
+};
+
+mod atapi;
+
+pub use self::atapi::AtapiDrive;
+
+const PRIMARY_IO: u16 = 0x1f0;
+const PRIMARY_CONTROL: u16 = 0x3f6;
+const SECONDARY_IO: u16 = 0x170;
+const SECONDARY_CONTROL: u16 = 0x376;
+
+// Status register
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+// Device control register
+const CONTROL_NIEN: u8 = 1 << 1;
+const CONTROL_SRST: u8 = 1 << 2;
+
+// Commands
+const CMD_READ_PIO: u8 = 0x20;
+const CMD_READ_PIO_EXT: u8 = 0x24;
+const CMD_WRITE_PIO: u8 = 0x30;
+const CMD_WRITE_PIO_EXT: u8 = 0x34;
+const CMD_CACHE_FLUSH: u8 = 0xe7;
+const CMD_CACHE_FLUSH_EXT: u8 = 0xea;
+const CMD_IDENTIFY: u8 = 0xec;
+
+const LBA28_MAX_SECTORS: u64 = 1 << 28;
+const LBA28_MAX_COUNT: u64 = 256;
+
+/// Number of status polls before giving up on the drive.
+const TIMEOUT: usize = 1_000_000;
+
+const WORDS_PER_SECTOR: usize = SECTOR_SIZE / 2;
+
+/// Names of the drives, in probing order, as given by qemu -hda to -hdd.
+const DRIVE_NAMES: [&str; 4] = ["hda", "hdb", "hdc", "hdd"];
+
+static BUSES: [Spinlock<Bus>; 2] = [
+    Spinlock::with_name(Bus::new(PRIMARY_IO, PRIMARY_CONTROL), "ATA0"),
+    Spinlock::with_name(Bus::new(SECONDARY_IO, SECONDARY_CONTROL), "ATA1"),
+];
+
+static DRIVES: Once<Vec<Arc<AtaDrive>>> = Once::with_name("ATA DRIVES");
+static CDROMS: Once<Vec<Arc<AtapiDrive>>> = Once::with_name("ATAPI DRIVES");
+
+/// Indicates differente error condition of an ATA drive.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Error {
+    /// Nothing answers on the bus or at this position.
+    NoDevice,
+    /// The drive is not a PATA disk, with its signature (ATAPI, SATA).
+    NotAta(u8, u8),
+    /// The drive stayed busy or never asked for data.
+    Timeout,
+    /// The drive reported a device fault.
+    DeviceFault,
+    /// The command failed, with the content of the error register.
+    Command(u8),
+    /// The packet command failed, with the sense key.
+    Sense(u8),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NoDevice => write!(f, "no device"),
+            Error::NotAta(0x14, 0xeb) => write!(f, "ATAPI device"),
+            Error::NotAta(0x3c, 0xc3) => write!(f, "SATA device"),
+            Error::NotAta(mid, high) => {
+                write!(f, "unknown device signature {:#04x}{:02x}", mid, high)
+            }
+            Error::Timeout => write!(f, "timeout"),
+            Error::DeviceFault => write!(f, "device fault"),
+            Error::Command(err) => {
+                const ERRORS: [&str; 8] = [
+                    "address mark not found",
+                    "track 0 not found",
+                    "command aborted",
+                    "media change request",
+                    "id not found",
+                    "media changed",
+                    "uncorrectable data",
+                    "bad block",
+                ];
+                write!(f, "command error {:#04x}", err)?;
+                for (bit, name) in ERRORS.iter().enumerate() {
+                    if err & (1 << bit) != 0 {
+                        write!(f, ", {}", name)?;
+                    }
+                }
+                Ok(())
+            }
+            Error::Sense(atapi::SENSE_NOT_READY) => write!(f, "no medium"),
+            Error::Sense(atapi::SENSE_UNIT_ATTENTION) => write!(f, "medium changed"),
+            Error::Sense(key) => write!(f, "packet command error, sense key {:#x}", key),
+        }
+    }
+}
+
+/// Registers of an ATA channel.
+pub struct Bus {
+    data: Port<u16>,
+    error: PortReadOnly<u8>,
+    features: PortWriteOnly<u8>,
+    sector_count: Port<u8>,
+    lba_low: Port<u8>,
+    lba_mid: Port<u8>,
+    lba_high: Port<u8>,
+    drive: Port<u8>,
+    status: PortReadOnly<u8>,
+    command: PortWriteOnly<u8>,
+    alt_status: PortReadOnly<u8>,
+    control: PortWriteOnly<u8>,
+}
+
+impl Bus {
+    pub const fn new(io: u16, control: u16) -> Self {
+        Self {
+            data: Port::new(io),
+            error: PortReadOnly::new(io + 1),
+            features: PortWriteOnly::new(io + 1),
+            sector_count: Port::new(io + 2),
+            lba_low: Port::new(io + 3),
+            lba_mid: Port::new(io + 4),
+            lba_high: Port::new(io + 5),
+            drive: Port::new(io + 6),
+            status: PortReadOnly::new(io + 7),
+            command: PortWriteOnly::new(io + 7),
+            alt_status: PortReadOnly::new(control),
+            control: PortWriteOnly::new(control),
+        }
+    }
+
+    /// Wait about 400ns by reading the alternate status 4 times.
+    fn delay(&mut self) {
+        for _ in 0..4 {
+            unsafe { self.alt_status.read() };
+        }
+    }
+
+    /// Reset both drives of the bus and disable their interrupts.
+    pub fn soft_reset(&mut self) -> Result<(), Error> {
+        unsafe {
+            self.control.write(CONTROL_SRST | CONTROL_NIEN);
+            for _ in 0..16 {
+                self.delay();
+            }
+            self.control.write(CONTROL_NIEN);
+        }
+        self.delay();
+        self.wait_not_busy().map(|_| ())
+    }
+
+    /// Wait until the drive is not busy and return its status.
+    fn wait_not_busy(&mut self) -> Result<u8, Error> {
+        for _ in 0..TIMEOUT {
+            let status = unsafe { self.alt_status.read() };
+            if status & STATUS_BSY == 0 {
+                return Ok(status);
+            }
+            core::hint::spin_loop();
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Wait until the drive asks for a data transfer.
+    fn wait_data(&mut self) -> Result<(), Error> {
+        for _ in 0..TIMEOUT {
+            let status = self.wait_not_busy()?;
+            self.check_status(status)?;
+            if status & STATUS_DRQ != 0 {
+                return Ok(());
+            }
+        }
+        Err(Error::Timeout)
+    }
+
+    /// Turn the error bits of a status into an error.
+    fn check_status(&mut self, status: u8) -> Result<(), Error> {
+        if status & STATUS_DF != 0 {
+            return Err(Error::DeviceFault);
+        }
+        if status & STATUS_ERR != 0 {
+            return Err(Error::Command(unsafe { self.error.read() }));
+        }
+        Ok(())
+    }
+
+    fn select(&mut self, slave: bool, bits: u8) -> Result<(), Error> {
+        self.wait_not_busy()?;
+        unsafe { self.drive.write(bits | (slave as u8) << 4) };
+        self.delay();
+        Ok(())
+    }
+
+    /// Send IDENTIFY DEVICE and return the 256 words it answers.
+    pub fn identify(&mut self, slave: bool) -> Result<[u16; WORDS_PER_SECTOR], Error> {
+        if unsafe { self.status.read() } == 0xff {
+            return Err(Error::NoDevice);
+        }
+        self.select(slave, 0xa0)?;
+        unsafe {
+            self.sector_count.write(0);
+            self.lba_low.write(0);
+            self.lba_mid.write(0);
+            self.lba_high.write(0);
+            self.command.write(CMD_IDENTIFY);
+        }
+        self.delay();
+        if unsafe { self.status.read() } == 0 {
+            return Err(Error::NoDevice);
+        }
+        self.wait_not_busy()?;
+        let signature = unsafe { (self.lba_mid.read(), self.lba_high.read()) };
+        if signature != (0, 0) {
+            return Err(Error::NotAta(signature.0, signature.1));
+        }
+        self.wait_data()?;
+        let mut words = [0; WORDS_PER_SECTOR];
+        unsafe { self.data.read_string(&mut words) };
+        Ok(words)
+    }
+
+    /// Load the registers for a transfer of count sectors from lba.
+    fn setup(&mut self, slave: bool, lba: u64, count: u64, lba48: bool) -> Result<(), Error> {
+        if lba48 {
+            self.select(slave, 0x40)?;
+            unsafe {
+                self.sector_count.write((count >> 8) as u8);
+                self.lba_low.write((lba >> 24) as u8);
+                self.lba_mid.write((lba >> 32) as u8);
+                self.lba_high.write((lba >> 40) as u8);
+            }
+        } else {
+            self.select(slave, 0xe0 | ((lba >> 24) as u8 & 0x0f))?;
+        }
+        unsafe {
+            self.sector_count.write(count as u8);
+            self.lba_low.write(lba as u8);
+            self.lba_mid.write((lba >> 8) as u8);
+            self.lba_high.write((lba >> 16) as u8);
+        }
+        Ok(())
+    }
+
+    /// Read buf.len() / 512 sectors from lba with PIO.
+    pub fn read_pio(&mut self, slave: bool, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let mut lba = lba;
+        for part in buf.chunks_mut(LBA28_MAX_COUNT as usize * SECTOR_SIZE) {
+            let count = (part.len() / SECTOR_SIZE) as u64;
+            let lba48 = needs_lba48(lba, count);
+            self.setup(slave, lba, count, lba48)?;
+            let command = if lba48 {
+                CMD_READ_PIO_EXT
+            } else {
+                CMD_READ_PIO
+            };
+            unsafe { self.command.write(command) };
+            self.delay();
+            let mut words = [0u16; WORDS_PER_SECTOR];
+            for sector in part.chunks_mut(SECTOR_SIZE) {
+                self.wait_data()?;
+                unsafe { self.data.read_string(&mut words) };
+                for (bytes, word) in sector.chunks_mut(2).zip(words.iter()) {
+                    bytes.copy_from_slice(&word.to_le_bytes());
+                }
+                self.delay();
+            }
+            lba += count;
+        }
+        Ok(())
+    }
+
+    /// Write buf.len() / 512 sectors to lba with PIO and flush the cache.
+    pub fn write_pio(&mut self, slave: bool, lba: u64, buf: &[u8]) -> Result<(), Error> {
+        let mut lba = lba;
+        let mut lba48 = false;
+        for part in buf.chunks(LBA28_MAX_COUNT as usize * SECTOR_SIZE) {
+            let count = (part.len() / SECTOR_SIZE) as u64;
+            lba48 |= needs_lba48(lba, count);
+            self.setup(slave, lba, count, lba48)?;
+            let command = if lba48 {
+                CMD_WRITE_PIO_EXT
+            } else {
+                CMD_WRITE_PIO
+            };
+            unsafe { self.command.write(command) };
+            self.delay();
+            let mut words = [0u16; WORDS_PER_SECTOR];
+            for sector in part.chunks(SECTOR_SIZE) {
+                for (word, bytes) in words.iter_mut().zip(sector.chunks(2)) {
+                    *word = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+                self.wait_data()?;
+                unsafe { self.data.write_string(&words) };
+                self.delay();
+            }
+            let status = self.wait_not_busy()?;
+            self.check_status(status)?;
+            lba += count;
+        }
+        let flush = if lba48 {
+            CMD_CACHE_FLUSH_EXT
+        } else {
+            CMD_CACHE_FLUSH
+        };
+        unsafe { self.command.write(flush) };
+        self.delay();
+        let status = self.wait_not_busy()?;
+        self.check_status(status)
+    }
+}
+
+/// Return true if the transfer can't be addressed with LBA28.
+fn needs_lba48(lba: u64, count: u64) -> bool {
+    lba + count > LBA28_MAX_SECTORS || count > LBA28_MAX_COUNT
+}
+
+/// Information returned by IDENTIFY DEVICE.
+#[derive(Debug, Clone, Copy)]
+pub struct DriveInfo {
+    model: [u8; 40],
+    serial: [u8; 20],
+    pub sectors: u64,
+    pub lba48: bool,
+}
+
+/// Copy an IDENTIFY string, stored as big endian words.
+fn identify_string(words: &[u16], out: &mut [u8]) {
+    for (bytes, word) in out.chunks_mut(2).zip(words.iter()) {
+        bytes.copy_from_slice(&word.to_be_bytes());
+    }
+}
+
+impl DriveInfo {
+    fn new(words: &[u16; WORDS_PER_SECTOR]) -> Self {
+        let mut info = Self {
+            model: [0; 40],
+            serial: [0; 20],
+            sectors: (words[60] as u64) | (words[61] as u64) << 16,
+            lba48: words[83] & (1 << 10) != 0,
+        };
+        identify_string(&words[27..47], &mut info.model);
+        identify_string(&words[10..20], &mut info.serial);
+        if info.lba48 {
+            info.sectors = (words[100] as u64)
+                | (words[101] as u64) << 16
+                | (words[102] as u64) << 32
+                | (words[103] as u64) << 48;
+        }
+        info
+    }
+
+    pub fn model(&self) -> &str {
+        from_utf8(&self.model).unwrap_or("?").trim()
+    }
+
+    pub fn serial(&self) -> &str {
+        from_utf8(&self.serial).unwrap_or("?").trim()
+    }
+}
+
+/// An ATA disk on one of the IDE channels.
+pub struct AtaDrive {
+    name: &'static str,
+    bus: &'static Spinlock<Bus>,
+    slave: bool,
+    info: DriveInfo,
+}
+
+impl AtaDrive {
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn info(&self) -> &DriveInfo {
+        &self.info
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn sector_count(&self) -> u64 {
+        self.info.sectors
+    }
+
+    fn read_sectors(&self, lba: u64, buf: &mut [u8]) -> Result<(), block::Error> {
+        let count = self.check_range(lba, buf.len())?;
+        // The transfers are split in commands of LBA28_MAX_COUNT sectors,
+        // only the address has to fit.
+        if !self.info.lba48 && lba + count > LBA28_MAX_SECTORS {
+            return Err(block::Error::OutOfRange);
+        }
+        Ok(self.bus.lock_irq().read_pio(self.slave, lba, buf)?)
+    }
+
+    fn write_sectors(&self, lba: u64, buf: &[u8]) -> Result<(), block::Error> {
+        let count = self.check_range(lba, buf.len())?;
+        if !self.info.lba48 && lba + count > LBA28_MAX_SECTORS {
+            return Err(block::Error::OutOfRange);
+        }
+        Ok(self.bus.lock_irq().write_pio(self.slave, lba, buf)?)
+    }
+}
+
+/// Reset both channels and identify the drives.
+pub fn init() {
+    let mut cdroms = Vec::new();
+    DRIVES.call_once(|| {
+        let mut drives = Vec::new();
+        for (i, bus) in BUSES.iter().enumerate() {
+            let mut bus_lock = bus.lock_irq();
+            if unsafe { bus_lock.status.read() } == 0xff || bus_lock.soft_reset().is_err() {
+                continue;
+            }
+            for slave in [false, true].iter() {
+                let name = DRIVE_NAMES[i * 2 + *slave as usize];
+                match bus_lock.identify(*slave) {
+                    Ok(words) => drives.push(Arc::new(AtaDrive {
+                        name,
+                        bus,
+                        slave: *slave,
+                        info: DriveInfo::new(&words),
+                    })),
+                    Err(Error::NotAta(mid, high)) if (mid, high) == atapi::SIGNATURE => {
+                        if let Ok(words) = bus_lock.identify_packet(*slave) {
+                            cdroms.push(Arc::new(AtapiDrive::new(name, bus, *slave, &words)));
+                        }
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+        drives
+    });
+    CDROMS.call_once(|| {
+        // Clear the unit attention of the reset and find the media.
+        for cdrom in cdroms.iter() {
+            let _ = cdrom.refresh();
+        }
+        cdroms
+    });
+}
+
+/// Return the detected drives.
+pub fn drives() -> &'static [Arc<AtaDrive>] {
+    &DRIVES
+}
+
+/// Return the drive with the given name (hda to hdd).
+pub fn find(name: &str) -> Option<Arc<AtaDrive>> {
+    drives().iter().find(|d| d.name == name).cloned()
+}
+
+/// Return the detected CD-ROM drives.
+pub fn cdroms() -> &'static [Arc<AtapiDrive>] {
+    &CDROMS
+}
+
+/// Return the CD-ROM drive with the given name (hda to hdd).
+pub fn find_cdrom(name: &str) -> Option<Arc<AtapiDrive>> {
+    cdroms().iter().find(|d| d.name() == name).cloned()
+}
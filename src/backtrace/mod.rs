@@ -0,0 +1,30 @@
+use core::arch::asm;
+
+/// Return the return address of the frame `skip` levels above the caller.
+///
+/// Walk the frame pointers, stopping at the end of the kernel stack.
+/// Return 0 if the chain ends before, or if the kernel was built without
+/// frame pointers, which the Makefile only keeps in the builds with the
+/// alloc_trace or lock_debug feature.
+#[inline(always)]
+pub fn return_address(skip: usize) -> usize {
+    if !cfg!(frame_pointers) {
+        return 0;
+    }
+    let bottom = crate::stack_bottom as *const () as usize;
+    let top = crate::stack_top as *const () as usize;
+    let mut ebp: usize;
+    unsafe {
+        asm!("mov {}, ebp", out(reg) ebp, options(nomem, nostack, preserves_flags));
+    }
+    for _ in 0..skip {
+        if ebp < bottom || ebp >= top {
+            return 0;
+        }
+        ebp = unsafe { *(ebp as *const usize) };
+    }
+    if ebp < bottom || ebp >= top {
+        return 0;
+    }
+    unsafe { *((ebp + 4) as *const usize) }
+}
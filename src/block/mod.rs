@@ -0,0 +1,110 @@
+use alloc::{vec, vec::Vec};
+use core::fmt;
+
+use crate::ata;
+
+pub const SECTOR_SIZE: usize = 512;
+
+/// Indicates differente error condition of a block device.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Error {
+    /// The sectors are past the end of the device.
+    OutOfRange,
+    /// The buffer is not a multiple of the sector size.
+    BufferSize,
+    /// The device can't be written.
+    ReadOnly,
+    Ata(ata::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OutOfRange => write!(f, "sector out of range"),
+            Error::BufferSize => write!(f, "buffer is not a multiple of the sector size"),
+            Error::ReadOnly => write!(f, "read-only device"),
+            Error::Ata(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<ata::Error> for Error {
+    fn from(e: ata::Error) -> Self {
+        Error::Ata(e)
+    }
+}
+
+/// A device read and written by sectors.
+pub trait BlockDevice: Send + Sync {
+    /// Size of a sector in bytes.
+    fn sector_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    /// Number of sectors of the device.
+    fn sector_count(&self) -> u64;
+
+    /// Read `buf.len() / sector_size()` sectors starting at lba.
+    fn read_sectors(&self, lba: u64, buf: &mut [u8]) -> Result<(), Error>;
+
+    /// Write `buf.len() / sector_size()` sectors starting at lba.
+    fn write_sectors(&self, lba: u64, buf: &[u8]) -> Result<(), Error>;
+
+    /// Return the number of sectors of buf after checking that they fit
+    /// in the device from lba.
+    fn check_range(&self, lba: u64, len: usize) -> Result<u64, Error> {
+        if len % self.sector_size() != 0 {
+            return Err(Error::BufferSize);
+        }
+        let count = (len / self.sector_size()) as u64;
+        match lba.checked_add(count) {
+            Some(end) if end <= self.sector_count() => Ok(count),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+}
+
+/// Read len bytes at offset of the device.
+pub fn read_bytes(device: &dyn BlockDevice, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+    let sector_size = device.sector_size() as u64;
+    let first = offset / sector_size;
+    let last = (offset + len as u64).div_ceil(sector_size);
+    let mut buf = vec![0; ((last - first) * sector_size) as usize];
+    device.read_sectors(first, &mut buf)?;
+    let start = (offset - first * sector_size) as usize;
+    Ok(buf[start..start + len].to_vec())
+}
+
+/// Write data at offset of the device, keeping the rest of the sectors.
+pub fn write_bytes(device: &dyn BlockDevice, offset: u64, data: &[u8]) -> Result<(), Error> {
+    let sector_size = device.sector_size() as u64;
+    let first = offset / sector_size;
+    let last = (offset + data.len() as u64).div_ceil(sector_size);
+    let mut buf = vec![0; ((last - first) * sector_size) as usize];
+    device.read_sectors(first, &mut buf)?;
+    let start = (offset - first * sector_size) as usize;
+    buf[start..start + data.len()].copy_from_slice(data);
+    device.write_sectors(first, &buf)
+}
+
+/// Read a little endian u16 at offset of data.
+pub fn read_u16(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+/// Read a little endian u32 at offset of data.
+pub fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Write value in little endian at offset of data.
+pub fn write_u16(data: &mut [u8], offset: usize, value: u16) {
+    data[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+/// Write value in little endian at offset of data.
+pub fn write_u32(data: &mut [u8], offset: usize, value: u32) {
+    data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
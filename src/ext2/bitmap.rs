@@ -0,0 +1,165 @@
+use alloc::vec;
+
+use super::Volume;
+use crate::vfs::Error;
+
+/// Set the first clear bit below count in bitmap and return its index.
+fn take_bit(bitmap: &mut [u8], count: usize) -> Option<usize> {
+    for (i, byte) in bitmap.iter_mut().enumerate().take((count + 7) / 8) {
+        if *byte == 0xff {
+            continue;
+        }
+        let bit = (!*byte).trailing_zeros() as usize;
+        if i * 8 + bit >= count {
+            return None;
+        }
+        *byte |= 1 << bit;
+        return Some(i * 8 + bit);
+    }
+    None
+}
+
+impl Volume {
+    /// Number of blocks of a group, the last one may be shorter.
+    fn blocks_in_group(&self, group: usize) -> usize {
+        let sb = self.superblock.lock();
+        let start = sb.first_data_block() as u64 + group as u64 * sb.blocks_per_group() as u64;
+        (sb.blocks_count() as u64 - start).min(sb.blocks_per_group() as u64) as usize
+    }
+
+    /// Return the group of an inode, to allocate its blocks nearby.
+    pub(super) fn inode_group(&self, ino: u32) -> usize {
+        ((ino - 1) / self.superblock.lock().inodes_per_group()) as usize
+    }
+
+    /// Allocate a zeroed block, from the group goal if it has room.
+    pub(super) fn alloc_block(&self, goal: usize) -> Result<u32, Error> {
+        self.check_writable()?;
+        let count = self.groups.lock().len();
+        let mut bitmap = vec![0; self.block_size];
+        for group in (goal..count).chain(0..goal) {
+            let mut desc = self.groups.lock()[group];
+            if desc.free_blocks_count == 0 {
+                continue;
+            }
+            self.read_block(desc.block_bitmap, &mut bitmap)?;
+            let bit = match take_bit(&mut bitmap, self.blocks_in_group(group)) {
+                Some(bit) => bit,
+                None => continue,
+            };
+            self.write_block(desc.block_bitmap, &bitmap)?;
+            desc.free_blocks_count -= 1;
+            self.groups.lock()[group] = desc;
+            self.write_group(group, &desc)?;
+            let block = {
+                let mut sb = self.superblock.lock();
+                let free = sb.free_blocks_count();
+                sb.set_free_blocks_count(free.saturating_sub(1));
+                sb.first_data_block() + (group as u32) * sb.blocks_per_group() + bit as u32
+            };
+            self.write_superblock()?;
+            self.write_block(block, &vec![0; self.block_size])?;
+            return Ok(block);
+        }
+        Err(Error::NoSpace)
+    }
+
+    pub(super) fn free_block(&self, block: u32) -> Result<(), Error> {
+        let (group, bit) = {
+            let sb = self.superblock.lock();
+            if block < sb.first_data_block() || block >= sb.blocks_count() {
+                return Err(Error::Corrupted);
+            }
+            let index = block - sb.first_data_block();
+            (
+                (index / sb.blocks_per_group()) as usize,
+                (index % sb.blocks_per_group()) as usize,
+            )
+        };
+        let mut desc = self.groups.lock()[group];
+        let mut bitmap = vec![0; self.block_size];
+        self.read_block(desc.block_bitmap, &mut bitmap)?;
+        if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+            return Err(Error::Corrupted);
+        }
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+        self.write_block(desc.block_bitmap, &bitmap)?;
+        desc.free_blocks_count += 1;
+        self.groups.lock()[group] = desc;
+        self.write_group(group, &desc)?;
+        {
+            let mut sb = self.superblock.lock();
+            let free = sb.free_blocks_count();
+            sb.set_free_blocks_count(free + 1);
+        }
+        self.write_superblock()
+    }
+
+    /// Allocate an inode, from the group goal if it has room.
+    pub(super) fn alloc_inode(&self, goal: usize, directory: bool) -> Result<u32, Error> {
+        self.check_writable()?;
+        let (inodes_per_group, first_ino) = {
+            let sb = self.superblock.lock();
+            (sb.inodes_per_group(), sb.first_ino())
+        };
+        let count = self.groups.lock().len();
+        let mut bitmap = vec![0; self.block_size];
+        for group in (goal..count).chain(0..goal) {
+            let mut desc = self.groups.lock()[group];
+            if desc.free_inodes_count == 0 {
+                continue;
+            }
+            self.read_block(desc.inode_bitmap, &mut bitmap)?;
+            let bit = match take_bit(&mut bitmap, inodes_per_group as usize) {
+                Some(bit) => bit,
+                None => continue,
+            };
+            let ino = group as u32 * inodes_per_group + bit as u32 + 1;
+            // The reserved inodes are normally marked used by mkfs.
+            if ino < first_ino {
+                return Err(Error::Corrupted);
+            }
+            self.write_block(desc.inode_bitmap, &bitmap)?;
+            desc.free_inodes_count -= 1;
+            if directory {
+                desc.used_dirs_count += 1;
+            }
+            self.groups.lock()[group] = desc;
+            self.write_group(group, &desc)?;
+            {
+                let mut sb = self.superblock.lock();
+                let free = sb.free_inodes_count();
+                sb.set_free_inodes_count(free.saturating_sub(1));
+            }
+            self.write_superblock()?;
+            return Ok(ino);
+        }
+        Err(Error::NoSpace)
+    }
+
+    pub(super) fn free_inode(&self, ino: u32, directory: bool) -> Result<(), Error> {
+        let inodes_per_group = self.superblock.lock().inodes_per_group();
+        let group = self.inode_group(ino);
+        let bit = ((ino - 1) % inodes_per_group) as usize;
+        let mut desc = self.groups.lock()[group];
+        let mut bitmap = vec![0; self.block_size];
+        self.read_block(desc.inode_bitmap, &mut bitmap)?;
+        if bitmap[bit / 8] & (1 << (bit % 8)) == 0 {
+            return Err(Error::Corrupted);
+        }
+        bitmap[bit / 8] &= !(1 << (bit % 8));
+        self.write_block(desc.inode_bitmap, &bitmap)?;
+        desc.free_inodes_count += 1;
+        if directory {
+            desc.used_dirs_count = desc.used_dirs_count.saturating_sub(1);
+        }
+        self.groups.lock()[group] = desc;
+        self.write_group(group, &desc)?;
+        {
+            let mut sb = self.superblock.lock();
+            let free = sb.free_inodes_count();
+            sb.set_free_inodes_count(free + 1);
+        }
+        self.write_superblock()
+    }
+}
@@ -0,0 +1,128 @@
+use alloc::vec::Vec;
+
+use crate::{
+    block::{read_u16, read_u32, write_u16, write_u32},
+    vfs::{Error, FileType},
+};
+
+/// Size of the fixed part of a directory entry.
+pub const HEADER_SIZE: usize = 8;
+
+/// A directory entry, ino is 0 for an unused one.
+pub struct RawDirEntry {
+    pub ino: u32,
+    /// Distance to the next entry.
+    pub rec_len: usize,
+    pub name: Vec<u8>,
+    /// Type from the entry if the filesystem has the filetype feature.
+    pub kind: Option<FileType>,
+}
+
+fn file_type(byte: u8) -> Option<FileType> {
+    match byte {
+        1 => Some(FileType::File),
+        2 => Some(FileType::Directory),
+        3 => Some(FileType::CharDevice),
+        4 => Some(FileType::BlockDevice),
+        5 => Some(FileType::Fifo),
+        6 => Some(FileType::Socket),
+        7 => Some(FileType::Symlink),
+        _ => None,
+    }
+}
+
+fn file_type_byte(kind: FileType) -> u8 {
+    match kind {
+        FileType::File => 1,
+        FileType::Directory => 2,
+        FileType::CharDevice => 3,
+        FileType::BlockDevice => 4,
+        FileType::Fifo => 5,
+        FileType::Socket => 6,
+        FileType::Symlink => 7,
+    }
+}
+
+/// Largest rec_len, of an entry filling a 64 KiB block, which doesn't
+/// fit the 16 bits on disk and is stored as 0xffff (or 0 by older Linux).
+const MAX_REC_LEN: usize = 1 << 16;
+
+fn rec_len_from_disk(raw: u16) -> usize {
+    match raw {
+        0xffff | 0 => MAX_REC_LEN,
+        _ => raw as usize,
+    }
+}
+
+fn rec_len_to_disk(rec_len: usize) -> u16 {
+    match rec_len {
+        MAX_REC_LEN => 0xffff,
+        _ => rec_len as u16,
+    }
+}
+
+/// Space taken by an entry with a name of name_len bytes.
+pub fn entry_size(name_len: usize) -> usize {
+    (HEADER_SIZE + name_len + 3) & !3
+}
+
+/// Write an entry at offset of a directory block.
+pub fn write(
+    block: &mut [u8],
+    offset: usize,
+    ino: u32,
+    rec_len: usize,
+    name: &[u8],
+    kind: FileType,
+    filetype: bool,
+) {
+    write_u32(block, offset, ino);
+    write_u16(block, offset + 4, rec_len_to_disk(rec_len));
+    if filetype {
+        block[offset + 6] = name.len() as u8;
+        block[offset + 7] = file_type_byte(kind);
+    } else {
+        write_u16(block, offset + 6, name.len() as u16);
+    }
+    block[offset + HEADER_SIZE..offset + HEADER_SIZE + name.len()].copy_from_slice(name);
+}
+
+pub fn set_ino(block: &mut [u8], offset: usize, ino: u32) {
+    write_u32(block, offset, ino);
+}
+
+pub fn set_rec_len(block: &mut [u8], offset: usize, rec_len: usize) {
+    write_u16(block, offset + 4, rec_len_to_disk(rec_len));
+}
+
+/// Parse the entry at offset of a directory block.
+pub fn parse(block: &[u8], offset: usize, filetype: bool) -> Result<RawDirEntry, Error> {
+    if offset + HEADER_SIZE > block.len() {
+        return Err(Error::Corrupted);
+    }
+    let ino = read_u32(block, offset);
+    let rec_len = rec_len_from_disk(read_u16(block, offset + 4));
+    let (name_len, kind) = if filetype {
+        (block[offset + 6] as usize, file_type(block[offset + 7]))
+    } else {
+        (read_u16(block, offset + 6) as usize, None)
+    };
+    if rec_len < HEADER_SIZE
+        || rec_len % 4 != 0
+        || offset + rec_len > block.len()
+        || (ino != 0 && HEADER_SIZE + name_len > rec_len)
+    {
+        return Err(Error::Corrupted);
+    }
+    let name = if ino != 0 {
+        block[offset + HEADER_SIZE..offset + HEADER_SIZE + name_len].to_vec()
+    } else {
+        Vec::new()
+    };
+    Ok(RawDirEntry {
+        ino,
+        rec_len,
+        name,
+        kind,
+    })
+}
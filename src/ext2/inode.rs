@@ -0,0 +1,993 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec,
+    vec::Vec,
+};
+use core::any::Any;
+
+use super::{dir, superblock, Volume};
+use crate::{
+    block::{read_u16, read_u32, write_u16, write_u32},
+    vfs::{DirEntry, Error, FileType, Inode, InodeRef, Metadata},
+};
+
+/// Number of block pointers of an inode: 12 direct, then the single,
+/// double and triple indirect ones.
+pub const N_BLOCKS: usize = 15;
+const N_DIRECT: usize = 12;
+const SINGLE_INDIRECT: usize = 12;
+const TRIPLE_INDIRECT: usize = 14;
+
+/// Longest name of a directory entry.
+const NAME_MAX: usize = 255;
+
+const S_IFMT: u16 = 0xf000;
+const S_IFSOCK: u16 = 0xc000;
+const S_IFLNK: u16 = 0xa000;
+const S_IFREG: u16 = 0x8000;
+const S_IFBLK: u16 = 0x6000;
+const S_IFDIR: u16 = 0x4000;
+const S_IFCHR: u16 = 0x2000;
+const S_IFIFO: u16 = 0x1000;
+
+/// The directory has a hash tree, which the writes don't maintain.
+const INDEX_FL: u32 = 0x0000_1000;
+/// The inode uses extents (ext4), not block pointers.
+const EXTENTS_FL: u32 = 0x0008_0000;
+
+/// Largest file without the large_file feature.
+const SMALL_FILE_MAX: u64 = 0x7fff_ffff;
+
+/// Magic number of an extended attribute block.
+const EA_MAGIC: u32 = 0xea02_0000;
+
+/// Symlinks shorter than this keep their target in the block pointers.
+const FAST_SYMLINK_MAX: u64 = 60;
+
+/// An inode as stored in the inode table.
+pub struct RawInode {
+    raw: Vec<u8>,
+}
+
+impl RawInode {
+    pub fn new(raw: Vec<u8>) -> Self {
+        Self { raw }
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn mode(&self) -> u16 {
+        read_u16(&self.raw, 0)
+    }
+
+    pub fn kind(&self) -> FileType {
+        match self.mode() & S_IFMT {
+            S_IFDIR => FileType::Directory,
+            S_IFLNK => FileType::Symlink,
+            S_IFCHR => FileType::CharDevice,
+            S_IFBLK => FileType::BlockDevice,
+            S_IFIFO => FileType::Fifo,
+            S_IFSOCK => FileType::Socket,
+            // Unknown modes are treated as regular files.
+            _ => FileType::File,
+        }
+    }
+
+    /// Size in bytes, the high 32 bits are only used by regular files.
+    pub fn size(&self, large_file: bool) -> u64 {
+        let low = read_u32(&self.raw, 4) as u64;
+        if large_file && self.kind() == FileType::File {
+            low | (read_u32(&self.raw, 108) as u64) << 32
+        } else {
+            low
+        }
+    }
+
+    pub fn set_size(&mut self, size: u64, large_file: bool) {
+        write_u32(&mut self.raw, 4, size as u32);
+        if large_file && self.kind() == FileType::File {
+            write_u32(&mut self.raw, 108, (size >> 32) as u32);
+        }
+    }
+
+    pub fn links_count(&self) -> u16 {
+        read_u16(&self.raw, 26)
+    }
+
+    pub fn set_links_count(&mut self, count: u16) {
+        write_u16(&mut self.raw, 26, count);
+    }
+
+    /// Number of 512 bytes sectors used, indirect blocks included.
+    pub fn sectors(&self) -> u32 {
+        read_u32(&self.raw, 28)
+    }
+
+    pub fn set_sectors(&mut self, sectors: u32) {
+        write_u32(&mut self.raw, 28, sectors);
+    }
+
+    pub fn flags(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn set_flags(&mut self, flags: u32) {
+        write_u32(&mut self.raw, 32, flags);
+    }
+
+    pub fn block(&self, i: usize) -> u32 {
+        read_u32(&self.raw, 40 + 4 * i)
+    }
+
+    pub fn set_block(&mut self, i: usize, block: u32) {
+        write_u32(&mut self.raw, 40 + 4 * i, block);
+    }
+
+    /// Block of the extended attributes.
+    pub fn file_acl(&self) -> u32 {
+        read_u32(&self.raw, 104)
+    }
+
+    pub fn set_file_acl(&mut self, block: u32) {
+        write_u32(&mut self.raw, 104, block);
+    }
+
+    /// Set the modification and change times.
+    pub fn touch(&mut self, time: u32) {
+        write_u32(&mut self.raw, 12, time);
+        write_u32(&mut self.raw, 16, time);
+    }
+
+    /// Bytes of the block pointers, where a fast symlink keeps its target.
+    fn block_bytes(&self) -> &[u8] {
+        &self.raw[40..40 + 4 * N_BLOCKS]
+    }
+
+    fn block_bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.raw[40..40 + 4 * N_BLOCKS]
+    }
+
+    /// Return true if the target of the symlink is in the inode.
+    pub fn is_fast_symlink(&self, block_size: usize) -> bool {
+        let acl_sectors = if self.file_acl() != 0 {
+            (block_size / 512) as u32
+        } else {
+            0
+        };
+        self.sectors() == acl_sectors
+    }
+}
+
+/// Inode of a mounted ext2 filesystem.
+pub struct Ext2Inode {
+    volume: Arc<Volume>,
+    ino: u32,
+}
+
+impl Ext2Inode {
+    pub(super) fn new(volume: Arc<Volume>, ino: u32) -> Self {
+        Self { volume, ino }
+    }
+
+    fn raw(&self) -> Result<RawInode, Error> {
+        self.volume.read_inode(self.ino)
+    }
+
+    fn write_raw(&self, inode: &RawInode) -> Result<(), Error> {
+        self.volume.write_inode(self.ino, inode)
+    }
+
+    /// Sectors of 512 bytes counted in the inode for each block.
+    fn block_sectors(&self) -> u32 {
+        (self.volume.block_size / 512) as u32
+    }
+
+    /// Largest size of a file, limited by the triple indirect blocks.
+    fn max_size(&self) -> u64 {
+        if !self.volume.large_file {
+            return SMALL_FILE_MAX;
+        }
+        let per_block = (self.volume.block_size / 4) as u64;
+        let blocks = N_DIRECT as u64 + per_block + per_block.pow(2) + per_block.pow(3);
+        blocks * self.volume.block_size as u64
+    }
+
+    /// Return the block pointer of the inode leading to the logical block
+    /// n, and the index of the pointer to follow in each indirect block.
+    fn block_path(&self, n: u64) -> Result<(usize, Vec<usize>), Error> {
+        if n < N_DIRECT as u64 {
+            return Ok((n as usize, Vec::new()));
+        }
+        let per_block = (self.volume.block_size / 4) as u64;
+        let mut n = n - N_DIRECT as u64;
+        let mut depth = 1;
+        let mut span = per_block;
+        while n >= span {
+            n -= span;
+            depth += 1;
+            if depth > TRIPLE_INDIRECT - SINGLE_INDIRECT + 1 {
+                return Err(Error::InvalidArgument);
+            }
+            span *= per_block;
+        }
+        // span is the number of data blocks under each pointer of the
+        // current level.
+        let mut path = Vec::with_capacity(depth);
+        for _ in 0..depth {
+            span /= per_block;
+            path.push((n / span) as usize);
+            n %= span;
+        }
+        Ok((SINGLE_INDIRECT + depth - 1, path))
+    }
+
+    /// Return the block holding the logical block n of the inode, 0 for a
+    /// hole.
+    fn bmap(&self, inode: &RawInode, n: u64) -> Result<u32, Error> {
+        if inode.flags() & EXTENTS_FL != 0 {
+            return Err(Error::NotSupported);
+        }
+        let (slot, path) = self.block_path(n)?;
+        let mut block = inode.block(slot);
+        let mut buf = vec![0; self.volume.block_size];
+        for index in path {
+            if block == 0 {
+                return Ok(0);
+            }
+            self.volume.read_block(block, &mut buf)?;
+            block = read_u32(&buf, index * 4);
+        }
+        Ok(block)
+    }
+
+    /// Return the block holding the logical block n of the inode,
+    /// allocating it and the missing indirect blocks for a hole.
+    ///
+    /// Only the copy of the inode is updated, the caller writes it back.
+    fn bmap_alloc(&self, inode: &mut RawInode, n: u64) -> Result<u32, Error> {
+        if inode.flags() & EXTENTS_FL != 0 {
+            return Err(Error::NotSupported);
+        }
+        let (slot, path) = self.block_path(n)?;
+        let goal = self.volume.inode_group(self.ino);
+        let mut block = inode.block(slot);
+        if block == 0 {
+            block = self.volume.alloc_block(goal)?;
+            inode.set_block(slot, block);
+            inode.set_sectors(inode.sectors() + self.block_sectors());
+        }
+        let mut buf = vec![0; self.volume.block_size];
+        for index in path {
+            self.volume.read_block(block, &mut buf)?;
+            let mut next = read_u32(&buf, index * 4);
+            if next == 0 {
+                next = self.volume.alloc_block(goal)?;
+                write_u32(&mut buf, index * 4, next);
+                self.volume.write_block(block, &buf)?;
+                inode.set_sectors(inode.sectors() + self.block_sectors());
+            }
+            block = next;
+        }
+        Ok(block)
+    }
+
+    /// Read the logical block n of the inode, zeros for a hole.
+    fn read_data_block(&self, inode: &RawInode, n: u64, buf: &mut [u8]) -> Result<(), Error> {
+        match self.bmap(inode, n)? {
+            0 => {
+                for b in buf.iter_mut() {
+                    *b = 0;
+                }
+                Ok(())
+            }
+            block => self.volume.read_block(block, buf),
+        }
+    }
+
+    /// Read from offset of the data, bounded by size.
+    fn read_data(
+        &self,
+        inode: &RawInode,
+        size: u64,
+        offset: u64,
+        buf: &mut [u8],
+    ) -> Result<usize, Error> {
+        if offset >= size {
+            return Ok(0);
+        }
+        let block_size = self.volume.block_size as u64;
+        let len = (buf.len() as u64).min(size - offset) as usize;
+        let mut block = vec![0; block_size as usize];
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % block_size) as usize;
+            let count = (block_size as usize - within).min(len - done);
+            self.read_data_block(inode, pos / block_size, &mut block)?;
+            buf[done..done + count].copy_from_slice(&block[within..within + count]);
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// Write buf at offset of the data, allocating the blocks, and count
+    /// the bytes written in done, which are kept after an error.
+    fn write_data(
+        &self,
+        inode: &mut RawInode,
+        offset: u64,
+        buf: &[u8],
+        done: &mut usize,
+    ) -> Result<(), Error> {
+        let block_size = self.volume.block_size as u64;
+        let mut block = vec![0; block_size as usize];
+        while *done < buf.len() {
+            let pos = offset + *done as u64;
+            let within = (pos % block_size) as usize;
+            let count = (block_size as usize - within).min(buf.len() - *done);
+            let n = self.bmap_alloc(inode, pos / block_size)?;
+            if count < block_size as usize {
+                self.volume.read_block(n, &mut block)?;
+            }
+            block[within..within + count].copy_from_slice(&buf[*done..*done + count]);
+            self.volume.write_block(n, &block)?;
+            *done += count;
+        }
+        Ok(())
+    }
+
+    /// Free the blocks of the inode after the first keep ones.
+    fn truncate_blocks(&self, inode: &mut RawInode, keep: u64) -> Result<(), Error> {
+        if inode.flags() & EXTENTS_FL != 0 {
+            return Err(Error::NotSupported);
+        }
+        let mut freed = 0;
+        let result = self.truncate_pointers(inode, keep, &mut freed);
+        inode.set_sectors(inode.sectors().saturating_sub(freed * self.block_sectors()));
+        result
+    }
+
+    fn truncate_pointers(
+        &self,
+        inode: &mut RawInode,
+        keep: u64,
+        freed: &mut u32,
+    ) -> Result<(), Error> {
+        for i in keep.min(N_DIRECT as u64) as usize..N_DIRECT {
+            let block = inode.block(i);
+            if block != 0 {
+                self.volume.free_block(block)?;
+                inode.set_block(i, 0);
+                *freed += 1;
+            }
+        }
+        let per_block = (self.volume.block_size / 4) as u64;
+        let mut base = N_DIRECT as u64;
+        let mut span = per_block;
+        for (depth, slot) in (SINGLE_INDIRECT..=TRIPLE_INDIRECT).enumerate() {
+            let block = inode.block(slot);
+            if block != 0 && self.truncate_indirect(block, depth + 1, base, keep, freed)? {
+                self.volume.free_block(block)?;
+                inode.set_block(slot, 0);
+                *freed += 1;
+            }
+            base += span;
+            span *= per_block;
+        }
+        Ok(())
+    }
+
+    /// Free the blocks after the first keep ones under an indirect block
+    /// of depth covering the data from the logical block base, and return
+    /// true if it has no more pointers to be freed itself.
+    fn truncate_indirect(
+        &self,
+        block: u32,
+        depth: usize,
+        base: u64,
+        keep: u64,
+        freed: &mut u32,
+    ) -> Result<bool, Error> {
+        let per_block = (self.volume.block_size / 4) as u64;
+        let span = per_block.pow(depth as u32 - 1);
+        if keep >= base + span * per_block {
+            return Ok(false);
+        }
+        let mut buf = vec![0; self.volume.block_size];
+        self.volume.read_block(block, &mut buf)?;
+        let mut changed = false;
+        for i in 0..per_block as usize {
+            let child = read_u32(&buf, i * 4);
+            let child_base = base + i as u64 * span;
+            if child == 0 || keep >= child_base + span {
+                continue;
+            }
+            let empty =
+                depth == 1 || self.truncate_indirect(child, depth - 1, child_base, keep, freed)?;
+            if empty {
+                self.volume.free_block(child)?;
+                write_u32(&mut buf, i * 4, 0);
+                *freed += 1;
+                changed = true;
+            }
+        }
+        let empty = buf.iter().all(|b| *b == 0);
+        if changed && !empty {
+            self.volume.write_block(block, &buf)?;
+        }
+        Ok(empty)
+    }
+
+    /// Set the size of a file, freeing the blocks after it.
+    fn resize(&self, inode: &mut RawInode, size: u64) -> Result<(), Error> {
+        if size > self.max_size() {
+            return Err(Error::NoSpace);
+        }
+        let block_size = self.volume.block_size as u64;
+        if size < inode.size(self.volume.large_file) {
+            self.truncate_blocks(inode, (size + block_size - 1) / block_size)?;
+            // Clear the end of the last block, read as zeros if the file
+            // grows again.
+            let within = (size % block_size) as usize;
+            let block = self.bmap(inode, size / block_size)?;
+            if within != 0 && block != 0 {
+                let mut buf = vec![0; block_size as usize];
+                self.volume.read_block(block, &mut buf)?;
+                for b in &mut buf[within..] {
+                    *b = 0;
+                }
+                self.volume.write_block(block, &buf)?;
+            }
+        }
+        inode.set_size(size, self.volume.large_file);
+        Ok(())
+    }
+
+    fn check_directory(&self, inode: &RawInode) -> Result<(), Error> {
+        match inode.kind() {
+            FileType::Directory => Ok(()),
+            _ => Err(Error::NotDirectory),
+        }
+    }
+
+    /// Return the entry at offset of the directory and the offset of the
+    /// next one, including the unused entries.
+    fn dir_entry_at(
+        &self,
+        inode: &RawInode,
+        offset: u64,
+    ) -> Result<Option<(dir::RawDirEntry, u64)>, Error> {
+        let size = inode.size(false);
+        if offset >= size {
+            return Ok(None);
+        }
+        let block_size = self.volume.block_size as u64;
+        let mut block = vec![0; block_size as usize];
+        self.read_data_block(inode, offset / block_size, &mut block)?;
+        let entry = dir::parse(
+            &block,
+            (offset % block_size) as usize,
+            self.volume.has_filetype,
+        )?;
+        let next = offset + entry.rec_len as u64;
+        Ok(Some((entry, next)))
+    }
+
+    /// Return the entry of the directory named name.
+    fn find_entry(&self, inode: &RawInode, name: &str) -> Result<Option<dir::RawDirEntry>, Error> {
+        let mut offset = 0;
+        while let Some((entry, next)) = self.dir_entry_at(inode, offset)? {
+            if entry.ino != 0 && entry.name == name.as_bytes() {
+                return Ok(Some(entry));
+            }
+            offset = next;
+        }
+        Ok(None)
+    }
+
+    /// Return true if the directory has no entries but `.` and `..`.
+    fn is_empty_directory(&self, inode: &RawInode) -> Result<bool, Error> {
+        let mut offset = 0;
+        while let Some((entry, next)) = self.dir_entry_at(inode, offset)? {
+            if entry.ino != 0 && entry.name != b"." && entry.name != b".." {
+                return Ok(false);
+            }
+            offset = next;
+        }
+        Ok(true)
+    }
+
+    /// Add an entry to the directory, in the unused space of a block or in
+    /// a new block at its end.
+    ///
+    /// A hash tree index is dropped, a directory without it stays valid.
+    fn add_entry(
+        &self,
+        inode: &mut RawInode,
+        name: &str,
+        ino: u32,
+        kind: FileType,
+    ) -> Result<(), Error> {
+        let name = name.as_bytes();
+        let needed = dir::entry_size(name.len());
+        let filetype = self.volume.has_filetype;
+        let block_size = self.volume.block_size;
+        let blocks = inode.size(false) / block_size as u64;
+        inode.set_flags(inode.flags() & !INDEX_FL);
+        let mut buf = vec![0; block_size];
+        for n in 0..blocks {
+            let block = self.bmap(inode, n)?;
+            if block == 0 {
+                return Err(Error::Corrupted);
+            }
+            self.volume.read_block(block, &mut buf)?;
+            let mut offset = 0;
+            while offset < block_size {
+                let entry = dir::parse(&buf, offset, filetype)?;
+                let used = match entry.ino {
+                    0 => 0,
+                    _ => dir::entry_size(entry.name.len()),
+                };
+                if entry.rec_len - used >= needed {
+                    if used != 0 {
+                        dir::set_rec_len(&mut buf, offset, used);
+                    }
+                    let rec_len = entry.rec_len - used;
+                    dir::write(&mut buf, offset + used, ino, rec_len, name, kind, filetype);
+                    return self.volume.write_block(block, &buf);
+                }
+                offset += entry.rec_len;
+            }
+        }
+        let block = self.bmap_alloc(inode, blocks)?;
+        let mut buf = vec![0; block_size];
+        dir::write(&mut buf, 0, ino, block_size, name, kind, filetype);
+        self.volume.write_block(block, &buf)?;
+        inode.set_size((blocks + 1) * block_size as u64, false);
+        Ok(())
+    }
+
+    /// Remove the entry named name from the directory, merging its space
+    /// into the previous entry of the block.
+    fn remove_entry(&self, inode: &mut RawInode, name: &str) -> Result<(), Error> {
+        let filetype = self.volume.has_filetype;
+        let block_size = self.volume.block_size;
+        let blocks = inode.size(false) / block_size as u64;
+        let mut buf = vec![0; block_size];
+        for n in 0..blocks {
+            let block = self.bmap(inode, n)?;
+            if block == 0 {
+                return Err(Error::Corrupted);
+            }
+            self.volume.read_block(block, &mut buf)?;
+            let mut offset = 0;
+            let mut previous = None;
+            while offset < block_size {
+                let entry = dir::parse(&buf, offset, filetype)?;
+                if entry.ino != 0 && entry.name == name.as_bytes() {
+                    match previous {
+                        Some((previous, rec_len)) => {
+                            dir::set_rec_len(&mut buf, previous, rec_len + entry.rec_len)
+                        }
+                        None => dir::set_ino(&mut buf, offset, 0),
+                    }
+                    inode.set_flags(inode.flags() & !INDEX_FL);
+                    return self.volume.write_block(block, &buf);
+                }
+                previous = Some((offset, entry.rec_len));
+                offset += entry.rec_len;
+            }
+        }
+        Err(Error::NotFound)
+    }
+
+    /// Point the `..` entry of the directory to parent.
+    fn set_parent(&self, inode: &RawInode, parent: u32) -> Result<(), Error> {
+        let block = self.bmap(inode, 0)?;
+        if block == 0 {
+            return Err(Error::Corrupted);
+        }
+        let mut buf = vec![0; self.volume.block_size];
+        self.volume.read_block(block, &mut buf)?;
+        let mut offset = 0;
+        while offset < buf.len() {
+            let entry = dir::parse(&buf, offset, self.volume.has_filetype)?;
+            if entry.ino != 0 && entry.name == b".." {
+                dir::set_ino(&mut buf, offset, parent);
+                return self.volume.write_block(block, &buf);
+            }
+            offset += entry.rec_len;
+        }
+        Err(Error::Corrupted)
+    }
+
+    /// Allocate an inode near this directory.
+    fn new_inode(&self, mode: u16) -> Result<(Ext2Inode, RawInode), Error> {
+        let directory = mode & S_IFMT == S_IFDIR;
+        let goal = self.volume.inode_group(self.ino);
+        let ino = self.volume.alloc_inode(goal, directory)?;
+        let (inode_size, extra_isize) = {
+            let sb = self.volume.superblock.lock();
+            (sb.inode_size(), sb.want_extra_isize())
+        };
+        let mut raw = RawInode::new(vec![0; inode_size]);
+        write_u16(&mut raw.raw, 0, mode);
+        let now = self.volume.now();
+        write_u32(&mut raw.raw, 8, now);
+        raw.touch(now);
+        raw.set_links_count(1);
+        if inode_size > superblock::GOOD_OLD_INODE_SIZE {
+            write_u16(&mut raw.raw, superblock::GOOD_OLD_INODE_SIZE, extra_isize);
+        }
+        Ok((Ext2Inode::new(self.volume.clone(), ino), raw))
+    }
+
+    /// Write the `.` and `..` entries of a new directory.
+    fn init_directory(&self, inode: &mut RawInode, parent: u32) -> Result<(), Error> {
+        let block_size = self.volume.block_size;
+        let filetype = self.volume.has_filetype;
+        let block = self.bmap_alloc(inode, 0)?;
+        let mut buf = vec![0; block_size];
+        let dot = dir::entry_size(1);
+        dir::write(
+            &mut buf,
+            0,
+            self.ino,
+            dot,
+            b".",
+            FileType::Directory,
+            filetype,
+        );
+        let rec_len = block_size - dot;
+        dir::write(
+            &mut buf,
+            dot,
+            parent,
+            rec_len,
+            b"..",
+            FileType::Directory,
+            filetype,
+        );
+        self.volume.write_block(block, &buf)?;
+        inode.set_size(block_size as u64, false);
+        inode.set_links_count(2);
+        Ok(())
+    }
+
+    /// Remove a link to the inode, a directory loses its `.` link too, and
+    /// release it when none is left.
+    ///
+    /// There is no count of the open files, an open inode is released as
+    /// well.
+    fn drop_link(&self) -> Result<(), Error> {
+        let mut inode = self.raw()?;
+        let links = match inode.kind() {
+            FileType::Directory => 0,
+            _ => inode.links_count().saturating_sub(1),
+        };
+        if links == 0 {
+            return self.release(inode);
+        }
+        inode.set_links_count(links);
+        inode.touch(self.volume.now());
+        self.write_raw(&inode)
+    }
+
+    /// Free the blocks and the inode itself.
+    fn release(&self, mut inode: RawInode) -> Result<(), Error> {
+        let directory = inode.kind() == FileType::Directory;
+        let fast_symlink =
+            inode.kind() == FileType::Symlink && inode.is_fast_symlink(self.volume.block_size);
+        if !fast_symlink {
+            self.truncate_blocks(&mut inode, 0)?;
+        }
+        let acl = inode.file_acl();
+        if acl != 0 {
+            self.release_acl(acl)?;
+            inode.set_file_acl(0);
+            inode.set_sectors(inode.sectors().saturating_sub(self.block_sectors()));
+        }
+        let now = self.volume.now();
+        inode.set_links_count(0);
+        inode.set_size(0, self.volume.large_file);
+        inode.touch(now);
+        // A deletion time of 0 would mark an inode in use.
+        write_u32(&mut inode.raw, 20, now.max(1));
+        self.write_raw(&inode)?;
+        self.volume.free_inode(self.ino, directory)
+    }
+
+    /// Drop a reference to a block of extended attributes, which may be
+    /// shared by several inodes.
+    fn release_acl(&self, block: u32) -> Result<(), Error> {
+        let mut buf = vec![0; self.volume.block_size];
+        self.volume.read_block(block, &mut buf)?;
+        let refcount = read_u32(&buf, 4);
+        if read_u32(&buf, 0) != EA_MAGIC {
+            return Err(Error::Corrupted);
+        }
+        if refcount > 1 {
+            write_u32(&mut buf, 4, refcount - 1);
+            return self.volume.write_block(block, &buf);
+        }
+        self.volume.free_block(block)
+    }
+
+    /// Create an entry name for a new inode of mode, its content written
+    /// by init.
+    fn add_child<F>(&self, name: &str, mode: u16, init: F) -> Result<InodeRef, Error>
+    where
+        F: FnOnce(&Ext2Inode, &mut RawInode) -> Result<(), Error>,
+    {
+        self.volume.check_writable()?;
+        if name.len() > NAME_MAX {
+            return Err(Error::NameTooLong);
+        }
+        let mut dir = self.raw()?;
+        self.check_directory(&dir)?;
+        if self.find_entry(&dir, name)?.is_some() {
+            return Err(Error::Exists);
+        }
+        let (child, mut inode) = self.new_inode(mode)?;
+        let kind = inode.kind();
+        let result = init(&child, &mut inode)
+            .and_then(|_| child.write_raw(&inode))
+            .and_then(|_| self.add_entry(&mut dir, name, child.ino, kind));
+        if result.is_ok() && kind == FileType::Directory {
+            dir.set_links_count(dir.links_count() + 1);
+        }
+        dir.touch(self.volume.now());
+        let result = result.and_then(|_| self.write_raw(&dir));
+        match result {
+            Ok(()) => Ok(Arc::new(child)),
+            Err(err) => {
+                let _ = child.release(inode);
+                Err(err)
+            }
+        }
+    }
+}
+
+impl Inode for Ext2Inode {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let inode = self.raw()?;
+        Ok(Metadata {
+            ino: self.ino as u64,
+            kind: inode.kind(),
+            size: inode.size(self.volume.large_file),
+            links: inode.links_count() as u32,
+            mode: inode.mode() & !S_IFMT,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let inode = self.raw()?;
+        match inode.kind() {
+            FileType::Directory => Err(Error::IsDirectory),
+            FileType::File => {
+                let size = inode.size(self.volume.large_file);
+                self.read_data(&inode, size, offset, buf)
+            }
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Error> {
+        self.volume.check_writable()?;
+        let mut inode = self.raw()?;
+        match inode.kind() {
+            FileType::File => {}
+            FileType::Directory => return Err(Error::IsDirectory),
+            _ => return Err(Error::InvalidArgument),
+        }
+        let max = self.max_size();
+        if offset >= max && !buf.is_empty() {
+            return Err(Error::NoSpace);
+        }
+        // The cap is taken in u64, max - offset doesn't fit a usize on i386.
+        let buf = &buf[..(buf.len() as u64).min(max - offset.min(max)) as usize];
+        let mut done = 0;
+        let result = self.write_data(&mut inode, offset, buf, &mut done);
+        // The blocks allocated before an error are kept.
+        let end = offset + done as u64;
+        if end > inode.size(self.volume.large_file) {
+            inode.set_size(end, self.volume.large_file);
+        }
+        inode.touch(self.volume.now());
+        self.write_raw(&inode)?;
+        match result {
+            Err(err) if done == 0 => Err(err),
+            _ => Ok(done),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> Result<(), Error> {
+        self.volume.check_writable()?;
+        let mut inode = self.raw()?;
+        match inode.kind() {
+            FileType::File => {}
+            FileType::Directory => return Err(Error::IsDirectory),
+            _ => return Err(Error::InvalidArgument),
+        }
+        let result = self.resize(&mut inode, size);
+        inode.touch(self.volume.now());
+        self.write_raw(&inode)?;
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, Error> {
+        let inode = self.raw()?;
+        self.check_directory(&inode)?;
+        match self.find_entry(&inode, name)? {
+            Some(entry) => Ok(Arc::new(Ext2Inode::new(self.volume.clone(), entry.ino))),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn readdir(&self, offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let inode = self.raw()?;
+        self.check_directory(&inode)?;
+        let mut offset = offset;
+        while let Some((entry, next)) = self.dir_entry_at(&inode, offset)? {
+            offset = next;
+            if entry.ino == 0 || entry.name == b"." || entry.name == b".." {
+                continue;
+            }
+            let kind = match entry.kind {
+                Some(kind) => kind,
+                None => self.volume.read_inode(entry.ino)?.kind(),
+            };
+            let entry = DirEntry {
+                name: String::from_utf8_lossy(&entry.name).to_string(),
+                ino: entry.ino as u64,
+                kind,
+            };
+            return Ok(Some((entry, offset)));
+        }
+        Ok(None)
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> Result<InodeRef, Error> {
+        match kind {
+            FileType::File => self.add_child(name, S_IFREG | 0o644, |_, _| Ok(())),
+            FileType::Directory => self.add_child(name, S_IFDIR | 0o755, |child, inode| {
+                child.init_directory(inode, self.ino)
+            }),
+            _ => Err(Error::NotSupported),
+        }
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<InodeRef, Error> {
+        let target = target.as_bytes();
+        if target.len() > self.volume.block_size {
+            return Err(Error::NameTooLong);
+        }
+        self.add_child(name, S_IFLNK | 0o777, |child, inode| {
+            if (target.len() as u64) < FAST_SYMLINK_MAX {
+                inode.block_bytes_mut()[..target.len()].copy_from_slice(target);
+            } else {
+                child.write_data(inode, 0, target, &mut 0)?;
+            }
+            inode.set_size(target.len() as u64, false);
+            Ok(())
+        })
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Error> {
+        self.volume.check_writable()?;
+        let mut dir = self.raw()?;
+        self.check_directory(&dir)?;
+        let entry = self.find_entry(&dir, name)?.ok_or(Error::NotFound)?;
+        let child = Ext2Inode::new(self.volume.clone(), entry.ino);
+        let directory = child.raw()?.kind() == FileType::Directory;
+        if directory && !child.is_empty_directory(&child.raw()?)? {
+            return Err(Error::NotEmpty);
+        }
+        self.remove_entry(&mut dir, name)?;
+        if directory {
+            dir.set_links_count(dir.links_count().saturating_sub(1));
+        }
+        dir.touch(self.volume.now());
+        self.write_raw(&dir)?;
+        child.drop_link()
+    }
+
+    fn rename(&self, name: &str, new_dir: &InodeRef, new_name: &str) -> Result<(), Error> {
+        let new_dir = match new_dir.as_any().downcast_ref::<Ext2Inode>() {
+            Some(dir) if Arc::ptr_eq(&dir.volume, &self.volume) => dir,
+            _ => return Err(Error::CrossDevice),
+        };
+        self.volume.check_writable()?;
+        if new_name.len() > NAME_MAX {
+            return Err(Error::NameTooLong);
+        }
+        let same = self.ino == new_dir.ino;
+        if same && name == new_name {
+            return Ok(());
+        }
+        let mut dir = self.raw()?;
+        self.check_directory(&dir)?;
+        let entry = self.find_entry(&dir, name)?.ok_or(Error::NotFound)?;
+        let source = Ext2Inode::new(self.volume.clone(), entry.ino);
+        let source_inode = source.raw()?;
+        let kind = source_inode.kind();
+        let directory = kind == FileType::Directory;
+        let mut other = match same {
+            true => None,
+            false => Some(new_dir.raw()?),
+        };
+        {
+            let target_dir = match other.as_mut() {
+                Some(inode) => inode,
+                None => &mut dir,
+            };
+            new_dir.check_directory(target_dir)?;
+            if let Some(existing) = new_dir.find_entry(target_dir, new_name)? {
+                // Two links to the same inode, like POSIX nothing is done.
+                if existing.ino == entry.ino {
+                    return Ok(());
+                }
+                let target = Ext2Inode::new(self.volume.clone(), existing.ino);
+                let target_inode = target.raw()?;
+                match (directory, target_inode.kind() == FileType::Directory) {
+                    (true, true) if !target.is_empty_directory(&target_inode)? => {
+                        return Err(Error::NotEmpty)
+                    }
+                    (true, false) => return Err(Error::NotDirectory),
+                    (false, true) => return Err(Error::IsDirectory),
+                    _ => {}
+                }
+                new_dir.remove_entry(target_dir, new_name)?;
+                if directory {
+                    target_dir.set_links_count(target_dir.links_count().saturating_sub(1));
+                }
+                target.drop_link()?;
+            }
+            new_dir.add_entry(target_dir, new_name, entry.ino, kind)?;
+            if directory && !same {
+                target_dir.set_links_count(target_dir.links_count() + 1);
+            }
+            target_dir.touch(self.volume.now());
+        }
+        self.remove_entry(&mut dir, name)?;
+        if directory && !same {
+            dir.set_links_count(dir.links_count().saturating_sub(1));
+            source.set_parent(&source_inode, new_dir.ino)?;
+        }
+        dir.touch(self.volume.now());
+        self.write_raw(&dir)?;
+        if let Some(inode) = other {
+            new_dir.write_raw(&inode)?;
+        }
+        Ok(())
+    }
+
+    fn readlink(&self) -> Result<String, Error> {
+        let inode = self.raw()?;
+        if inode.kind() != FileType::Symlink {
+            return Err(Error::InvalidArgument);
+        }
+        let size = inode.size(false);
+        let target = if inode.is_fast_symlink(self.volume.block_size) {
+            if size > FAST_SYMLINK_MAX {
+                return Err(Error::Corrupted);
+            }
+            inode.block_bytes()[..size as usize].to_vec()
+        } else {
+            if size > self.volume.block_size as u64 {
+                return Err(Error::Corrupted);
+            }
+            let mut target = vec![0; size as usize];
+            self.read_data(&inode, size, 0, &mut target)?;
+            target
+        };
+        String::from_utf8(target).map_err(|_| Error::Corrupted)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
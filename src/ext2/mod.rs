@@ -0,0 +1,195 @@
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use crate::{
+    block::{self, BlockDevice},
+    spinlock::Spinlock,
+    vfs::{Error, FileSystem, InodeRef},
+};
+
+mod bitmap;
+mod dir;
+mod inode;
+mod superblock;
+
+use self::{
+    inode::{Ext2Inode, RawInode},
+    superblock::{GroupDesc, Superblock},
+};
+
+/// Inode of the root directory.
+const ROOT_INO: u32 = 2;
+
+/// An ext2 filesystem on a block device.
+pub struct Ext2Fs {
+    volume: Arc<Volume>,
+}
+
+/// State of a mounted ext2 filesystem shared by its inodes.
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    superblock: Spinlock<Superblock>,
+    groups: Spinlock<Vec<GroupDesc>>,
+    block_size: usize,
+    /// Sectors of the device per block.
+    block_sectors: u64,
+    has_filetype: bool,
+    large_file: bool,
+    /// False if the filesystem has features the writes would break.
+    writable: bool,
+}
+
+impl Volume {
+    fn check_writable(&self) -> Result<(), Error> {
+        match self.writable {
+            true => Ok(()),
+            false => Err(Error::ReadOnly),
+        }
+    }
+
+    /// Timestamp of the new and deleted inodes.
+    fn now(&self) -> u32 {
+        self.superblock.lock().wtime()
+    }
+
+    /// Read a block of the filesystem, buf is one block long.
+    fn read_block(&self, block: u32, buf: &mut [u8]) -> Result<(), Error> {
+        if block == 0 || block >= self.superblock.lock().blocks_count() {
+            return Err(Error::Corrupted);
+        }
+        self.device
+            .read_sectors(block as u64 * self.block_sectors, buf)?;
+        Ok(())
+    }
+
+    fn write_block(&self, block: u32, buf: &[u8]) -> Result<(), Error> {
+        if block == 0 || block >= self.superblock.lock().blocks_count() {
+            return Err(Error::Corrupted);
+        }
+        self.device
+            .write_sectors(block as u64 * self.block_sectors, buf)?;
+        Ok(())
+    }
+
+    /// Write back the primary superblock, the backups are left to fsck.
+    fn write_superblock(&self) -> Result<(), Error> {
+        let sb = self.superblock.lock();
+        block::write_bytes(
+            &*self.device,
+            superblock::SUPERBLOCK_OFFSET as u64,
+            sb.raw(),
+        )?;
+        Ok(())
+    }
+
+    /// Write back the descriptor of a group in the primary table.
+    fn write_group(&self, group: usize, desc: &GroupDesc) -> Result<(), Error> {
+        let table = (self.superblock.lock().first_data_block() as usize + 1) * self.block_size;
+        let offset = table + group * superblock::GROUP_DESC_SIZE;
+        let mut raw = block::read_bytes(&*self.device, offset as u64, superblock::GROUP_DESC_SIZE)?;
+        desc.write(&mut raw);
+        block::write_bytes(&*self.device, offset as u64, &raw)?;
+        Ok(())
+    }
+
+    /// Return the block and the byte offset in it of an inode.
+    fn inode_location(&self, ino: u32) -> Result<(u32, usize), Error> {
+        let (inodes_count, inodes_per_group, inode_size) = {
+            let sb = self.superblock.lock();
+            (sb.inodes_count(), sb.inodes_per_group(), sb.inode_size())
+        };
+        if ino == 0 || ino > inodes_count {
+            return Err(Error::Corrupted);
+        }
+        let group = ((ino - 1) / inodes_per_group) as usize;
+        let index = ((ino - 1) % inodes_per_group) as usize;
+        let table = self.groups.lock()[group].inode_table;
+        let offset = index * inode_size;
+        Ok((
+            table + (offset / self.block_size) as u32,
+            offset % self.block_size,
+        ))
+    }
+
+    fn read_inode(&self, ino: u32) -> Result<RawInode, Error> {
+        let (block, offset) = self.inode_location(ino)?;
+        let inode_size = self.superblock.lock().inode_size();
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        Ok(RawInode::new(buf[offset..offset + inode_size].to_vec()))
+    }
+
+    fn write_inode(&self, ino: u32, inode: &RawInode) -> Result<(), Error> {
+        let (block, offset) = self.inode_location(ino)?;
+        let mut buf = vec![0; self.block_size];
+        self.read_block(block, &mut buf)?;
+        buf[offset..offset + inode.raw().len()].copy_from_slice(inode.raw());
+        self.write_block(block, &buf)
+    }
+}
+
+impl Ext2Fs {
+    /// Read the superblock and the group descriptors of device.
+    ///
+    /// Filesystems with features changing the layout are refused, the ones
+    /// with features the writes don't maintain are read-only.
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Self, Error> {
+        let raw = block::read_bytes(
+            &*device,
+            superblock::SUPERBLOCK_OFFSET as u64,
+            superblock::SUPERBLOCK_SIZE,
+        )?;
+        let sb = Superblock::parse(raw)?;
+        let block_size = sb.block_size();
+        if block_size % device.sector_size() != 0
+            || sb.blocks_count() as u64 * (block_size / device.sector_size()) as u64
+                > device.sector_count()
+        {
+            return Err(Error::Corrupted);
+        }
+        let count = sb.group_count();
+        let table = block::read_bytes(
+            &*device,
+            (sb.first_data_block() as u64 + 1) * block_size as u64,
+            count * superblock::GROUP_DESC_SIZE,
+        )?;
+        let groups: Vec<GroupDesc> = table
+            .chunks(superblock::GROUP_DESC_SIZE)
+            .map(GroupDesc::parse)
+            .collect();
+        let inode_table_blocks =
+            (sb.inodes_per_group() as usize * sb.inode_size() + block_size - 1) / block_size;
+        for group in &groups {
+            if group.block_bitmap == 0
+                || group.block_bitmap >= sb.blocks_count()
+                || group.inode_bitmap == 0
+                || group.inode_bitmap >= sb.blocks_count()
+                || group.inode_table == 0
+                || group.inode_table as u64 + inode_table_blocks as u64 > sb.blocks_count() as u64
+            {
+                return Err(Error::Corrupted);
+            }
+        }
+        Ok(Self {
+            volume: Arc::new(Volume {
+                block_sectors: (block_size / device.sector_size()) as u64,
+                device,
+                has_filetype: sb.has_filetype(),
+                large_file: sb.feature_ro_compat() & superblock::RO_COMPAT_LARGE_FILE != 0,
+                writable: sb.is_writable(),
+                superblock: Spinlock::with_name(sb, "EXT2_SUPERBLOCK"),
+                groups: Spinlock::with_name(groups, "EXT2_GROUPS"),
+                block_size,
+            }),
+        })
+    }
+}
+
+impl FileSystem for Ext2Fs {
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+
+    fn root(&self) -> Result<InodeRef, Error> {
+        Ok(Arc::new(Ext2Inode::new(self.volume.clone(), ROOT_INO)))
+    }
+}
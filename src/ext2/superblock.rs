@@ -0,0 +1,217 @@
+use alloc::vec::Vec;
+
+use crate::{
+    block::{read_u16, read_u32, write_u16, write_u32},
+    vfs::Error,
+};
+
+/// Byte offset of the superblock on the device.
+pub const SUPERBLOCK_OFFSET: usize = 1024;
+pub const SUPERBLOCK_SIZE: usize = 1024;
+
+const EXT2_MAGIC: u16 = 0xef53;
+
+const REV_GOOD_OLD: u32 = 0;
+const REV_DYNAMIC: u32 = 1;
+
+/// Inode size and first usable inode of the revision 0.
+pub const GOOD_OLD_INODE_SIZE: usize = 128;
+const GOOD_OLD_FIRST_INO: u32 = 11;
+
+/// Largest block size, 64 KiB.
+const MAX_LOG_BLOCK_SIZE: u32 = 6;
+
+/// Directory entries have a file type byte.
+pub const INCOMPAT_FILETYPE: u32 = 0x0002;
+/// Incompatible features that the driver understands, the others (like
+/// compression, journal recovery, extents or 64 bit) change the layout.
+const INCOMPAT_SUPPORTED: u32 = INCOMPAT_FILETYPE;
+
+/// Backups of the superblock only in some groups.
+const RO_COMPAT_SPARSE_SUPER: u32 = 0x0001;
+/// Files can be larger than 4 GiB.
+pub const RO_COMPAT_LARGE_FILE: u32 = 0x0002;
+/// Read-only compatible features kept consistent by the writes, a
+/// filesystem with others (like checksums) is mounted read-only.
+const RO_COMPAT_SUPPORTED: u32 = RO_COMPAT_SPARSE_SUPER | RO_COMPAT_LARGE_FILE;
+
+pub const GROUP_DESC_SIZE: usize = 32;
+
+/// Superblock, the raw bytes are kept to be written back unchanged.
+pub struct Superblock {
+    raw: Vec<u8>,
+}
+
+impl Superblock {
+    /// Check the magic number, revision and features of the superblock.
+    pub fn parse(raw: Vec<u8>) -> Result<Self, Error> {
+        let sb = Self { raw };
+        if read_u16(&sb.raw, 56) != EXT2_MAGIC {
+            return Err(Error::InvalidArgument);
+        }
+        if sb.rev_level() > REV_DYNAMIC {
+            return Err(Error::NotSupported);
+        }
+        if sb.feature_incompat() & !INCOMPAT_SUPPORTED != 0 {
+            return Err(Error::NotSupported);
+        }
+        if read_u32(&sb.raw, 24) > MAX_LOG_BLOCK_SIZE {
+            return Err(Error::Corrupted);
+        }
+        let bits_per_block = (sb.block_size() * 8) as u32;
+        let inode_size = sb.inode_size();
+        if sb.blocks_per_group() == 0
+            || sb.blocks_per_group() > bits_per_block
+            || sb.inodes_per_group() == 0
+            || sb.inodes_per_group() > bits_per_block
+            || sb.first_data_block() >= sb.blocks_count()
+            || inode_size < GOOD_OLD_INODE_SIZE
+            || !inode_size.is_power_of_two()
+            || inode_size > sb.block_size()
+            || (sb.group_count() as u64) * (sb.inodes_per_group() as u64) < sb.inodes_count() as u64
+        {
+            return Err(Error::Corrupted);
+        }
+        Ok(sb)
+    }
+
+    pub fn raw(&self) -> &[u8] {
+        &self.raw
+    }
+
+    pub fn inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 0)
+    }
+
+    pub fn blocks_count(&self) -> u32 {
+        read_u32(&self.raw, 4)
+    }
+
+    pub fn free_blocks_count(&self) -> u32 {
+        read_u32(&self.raw, 12)
+    }
+
+    pub fn set_free_blocks_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 12, count)
+    }
+
+    pub fn free_inodes_count(&self) -> u32 {
+        read_u32(&self.raw, 16)
+    }
+
+    pub fn set_free_inodes_count(&mut self, count: u32) {
+        write_u32(&mut self.raw, 16, count)
+    }
+
+    pub fn first_data_block(&self) -> u32 {
+        read_u32(&self.raw, 20)
+    }
+
+    pub fn block_size(&self) -> usize {
+        1024 << read_u32(&self.raw, 24)
+    }
+
+    pub fn blocks_per_group(&self) -> u32 {
+        read_u32(&self.raw, 32)
+    }
+
+    pub fn inodes_per_group(&self) -> u32 {
+        read_u32(&self.raw, 40)
+    }
+
+    /// Last write time, used as the current time as there is no clock.
+    pub fn wtime(&self) -> u32 {
+        read_u32(&self.raw, 48)
+    }
+
+    pub fn rev_level(&self) -> u32 {
+        read_u32(&self.raw, 76)
+    }
+
+    /// First inode usable for the files, the ones before are reserved.
+    pub fn first_ino(&self) -> u32 {
+        match self.rev_level() {
+            REV_GOOD_OLD => GOOD_OLD_FIRST_INO,
+            _ => read_u32(&self.raw, 84),
+        }
+    }
+
+    pub fn inode_size(&self) -> usize {
+        match self.rev_level() {
+            REV_GOOD_OLD => GOOD_OLD_INODE_SIZE,
+            _ => read_u16(&self.raw, 88) as usize,
+        }
+    }
+
+    pub fn feature_incompat(&self) -> u32 {
+        match self.rev_level() {
+            REV_GOOD_OLD => 0,
+            _ => read_u32(&self.raw, 96),
+        }
+    }
+
+    pub fn feature_ro_compat(&self) -> u32 {
+        match self.rev_level() {
+            REV_GOOD_OLD => 0,
+            _ => read_u32(&self.raw, 100),
+        }
+    }
+
+    /// Size of the extra fields that new large inodes should have.
+    pub fn want_extra_isize(&self) -> u16 {
+        match self.inode_size() {
+            GOOD_OLD_INODE_SIZE => 0,
+            _ => read_u16(&self.raw, 350),
+        }
+    }
+
+    /// Return true if the driver can write without breaking a feature.
+    pub fn is_writable(&self) -> bool {
+        self.feature_ro_compat() & !RO_COMPAT_SUPPORTED == 0
+    }
+
+    /// Number of block groups.
+    pub fn group_count(&self) -> usize {
+        let blocks = (self.blocks_count() - self.first_data_block()) as u64;
+        let per_group = self.blocks_per_group() as u64;
+        ((blocks + per_group - 1) / per_group) as usize
+    }
+
+    pub fn has_filetype(&self) -> bool {
+        self.feature_incompat() & INCOMPAT_FILETYPE != 0
+    }
+}
+
+/// Block group descriptor.
+#[derive(Debug, Copy, Clone)]
+pub struct GroupDesc {
+    pub block_bitmap: u32,
+    pub inode_bitmap: u32,
+    pub inode_table: u32,
+    pub free_blocks_count: u16,
+    pub free_inodes_count: u16,
+    pub used_dirs_count: u16,
+}
+
+impl GroupDesc {
+    pub fn parse(raw: &[u8]) -> Self {
+        Self {
+            block_bitmap: read_u32(raw, 0),
+            inode_bitmap: read_u32(raw, 4),
+            inode_table: read_u32(raw, 8),
+            free_blocks_count: read_u16(raw, 12),
+            free_inodes_count: read_u16(raw, 14),
+            used_dirs_count: read_u16(raw, 16),
+        }
+    }
+
+    /// Write the descriptor in raw, the other bytes are unchanged.
+    pub fn write(&self, raw: &mut [u8]) {
+        write_u32(raw, 0, self.block_bitmap);
+        write_u32(raw, 4, self.inode_bitmap);
+        write_u32(raw, 8, self.inode_table);
+        write_u16(raw, 12, self.free_blocks_count);
+        write_u16(raw, 14, self.free_inodes_count);
+        write_u16(raw, 16, self.used_dirs_count);
+    }
+}
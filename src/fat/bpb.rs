@@ -0,0 +1,144 @@
+use crate::{
+    block::{read_u16, read_u32},
+    vfs::Error,
+};
+
+/// Size of the boot sector read at mount.
+pub const BOOT_SECTOR_SIZE: usize = 512;
+
+const BOOT_SIGNATURE: u16 = 0xaa55;
+
+/// Clusters counts from which the FAT entries are 16 and 32 bits wide.
+const FAT16_MIN_CLUSTERS: u32 = 4085;
+const FAT32_MIN_CLUSTERS: u32 = 65525;
+
+/// Only the active FAT is used, instead of all of them mirrored.
+const NO_MIRRORING: u16 = 0x0080;
+
+/// Width of the entries of the file allocation table.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+/// BIOS parameter block, the layout of the volume from its boot sector.
+#[derive(Debug, Copy, Clone)]
+pub struct Bpb {
+    pub bytes_per_sector: u32,
+    pub sectors_per_cluster: u32,
+    pub reserved_sectors: u32,
+    pub fat_count: u32,
+    /// Number of entries of the fixed root directory, 0 for FAT32.
+    pub root_entries: u32,
+    pub total_sectors: u32,
+    /// Sectors of each FAT.
+    pub fat_size: u32,
+    /// FAT32 only fields.
+    pub root_cluster: u32,
+    pub fsinfo_sector: u32,
+    ext_flags: u16,
+}
+
+impl Bpb {
+    /// Parse and check the boot sector.
+    pub fn parse(raw: &[u8]) -> Result<Self, Error> {
+        if read_u16(raw, 510) != BOOT_SIGNATURE || (raw[0] != 0xeb && raw[0] != 0xe9) {
+            return Err(Error::InvalidArgument);
+        }
+        let fat_size = match read_u16(raw, 22) {
+            0 => read_u32(raw, 36),
+            size => size as u32,
+        };
+        let total_sectors = match read_u16(raw, 19) {
+            0 => read_u32(raw, 32),
+            count => count as u32,
+        };
+        let bpb = Self {
+            bytes_per_sector: read_u16(raw, 11) as u32,
+            sectors_per_cluster: raw[13] as u32,
+            reserved_sectors: read_u16(raw, 14) as u32,
+            fat_count: raw[16] as u32,
+            root_entries: read_u16(raw, 17) as u32,
+            total_sectors,
+            fat_size,
+            root_cluster: read_u32(raw, 44),
+            fsinfo_sector: read_u16(raw, 48) as u32,
+            ext_flags: read_u16(raw, 40),
+        };
+        if !bpb.bytes_per_sector.is_power_of_two()
+            || bpb.bytes_per_sector < 512
+            || bpb.bytes_per_sector > 4096
+            || !bpb.sectors_per_cluster.is_power_of_two()
+            || bpb.reserved_sectors == 0
+            || bpb.fat_count == 0
+            || bpb.fat_size == 0
+            || bpb.reserved_sectors as u64
+                + bpb.fat_count as u64 * bpb.fat_size as u64
+                + bpb.root_sectors() as u64
+                >= bpb.total_sectors as u64
+        {
+            return Err(Error::Corrupted);
+        }
+        let fat_entries = bpb.fat_size as u64 * bpb.bytes_per_sector as u64 * 8
+            / match bpb.fat_type() {
+                FatType::Fat12 => 12,
+                FatType::Fat16 => 16,
+                FatType::Fat32 => 32,
+            };
+        if fat_entries < bpb.cluster_count() as u64 + 2 {
+            return Err(Error::Corrupted);
+        }
+        if bpb.fat_type() == FatType::Fat32
+            && (bpb.root_entries != 0
+                || bpb.root_cluster < 2
+                || bpb.root_cluster >= bpb.cluster_count() + 2)
+        {
+            return Err(Error::Corrupted);
+        }
+        if bpb.fat_type() != FatType::Fat32 && bpb.root_entries == 0 {
+            return Err(Error::Corrupted);
+        }
+        Ok(bpb)
+    }
+
+    /// Sectors of the fixed root directory.
+    pub fn root_sectors(&self) -> u32 {
+        (self.root_entries * 32).div_ceil(self.bytes_per_sector)
+    }
+
+    /// First sector of the fixed root directory.
+    pub fn root_sector(&self) -> u32 {
+        self.reserved_sectors + self.fat_count * self.fat_size
+    }
+
+    /// First sector of the cluster 2.
+    pub fn data_sector(&self) -> u32 {
+        self.root_sector() + self.root_sectors()
+    }
+
+    /// Number of data clusters, numbered from 2.
+    pub fn cluster_count(&self) -> u32 {
+        (self.total_sectors - self.data_sector()) / self.sectors_per_cluster
+    }
+
+    /// The type only depends on the number of clusters.
+    pub fn fat_type(&self) -> FatType {
+        match self.cluster_count() {
+            count if count < FAT16_MIN_CLUSTERS => FatType::Fat12,
+            count if count < FAT32_MIN_CLUSTERS => FatType::Fat16,
+            _ => FatType::Fat32,
+        }
+    }
+
+    /// The only FAT in use if the mirroring is disabled (FAT32).
+    pub fn active_fat(&self) -> Option<u32> {
+        match self.fat_type() {
+            FatType::Fat32 if self.ext_flags & NO_MIRRORING != 0 => {
+                Some((self.ext_flags & 0xf) as u32).filter(|fat| *fat < self.fat_count)
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,611 @@
+use alloc::{string::String, vec, vec::Vec};
+use core::{char, mem, ops::ControlFlow};
+
+use super::Volume;
+use crate::{
+    block::{read_u16, read_u32, write_u16, write_u32},
+    vfs::Error,
+};
+
+/// Size of a directory entry.
+pub const ENTRY_SIZE: usize = 32;
+
+pub const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_VOLUME_ID: u8 = 0x08;
+pub const ATTR_DIRECTORY: u8 = 0x10;
+pub const ATTR_ARCHIVE: u8 = 0x20;
+/// Attributes of a long name entry, a combination no short entry has.
+const ATTR_LONG_NAME: u8 = 0x0f;
+
+/// First byte of a deleted entry.
+pub const DELETED: u8 = 0xe5;
+/// First byte of the entry ending the directory.
+const END: u8 = 0x00;
+/// First byte of a short name starting with 0xe5.
+const KANJI_E5: u8 = 0x05;
+
+/// Flags of the reserved byte for short names displayed in lower case,
+/// as written by Windows NT and Linux.
+const LOWER_BASE: u8 = 0x08;
+const LOWER_EXT: u8 = 0x10;
+
+/// Characters of a name in a long name entry.
+const LONG_NAME_CHARS: usize = 13;
+/// Offsets of the characters in a long name entry.
+const LONG_NAME_OFFSETS: [usize; LONG_NAME_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Flag of the order byte of the last long name entry, the first one on
+/// the disk.
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// Longest name in UTF-16 units.
+const NAME_MAX: usize = 255;
+/// Largest number of slots of a directory.
+const DIR_ENTRIES_MAX: u64 = 65536;
+
+/// Short names of the `.` and `..` entries.
+pub const DOT: [u8; 11] = *b".          ";
+pub const DOT_DOT: [u8; 11] = *b"..         ";
+
+/// A directory entry, short or part of a long name.
+#[derive(Copy, Clone)]
+pub struct RawEntry {
+    pub raw: [u8; ENTRY_SIZE],
+}
+
+impl RawEntry {
+    pub fn parse(raw: &[u8]) -> Self {
+        let mut entry = Self {
+            raw: [0; ENTRY_SIZE],
+        };
+        entry.raw.copy_from_slice(&raw[..ENTRY_SIZE]);
+        entry
+    }
+
+    /// A short entry with a name and its case flags.
+    pub fn new(name: &[u8; 11], case: u8, attr: u8) -> Self {
+        let mut entry = Self {
+            raw: [0; ENTRY_SIZE],
+        };
+        entry.set_short_name(name, case);
+        entry.raw[11] = attr;
+        entry
+    }
+
+    /// Return true for a deleted entry or one after the end.
+    pub fn is_free(&self) -> bool {
+        self.raw[0] == DELETED || self.raw[0] == END
+    }
+
+    pub fn is_end(&self) -> bool {
+        self.raw[0] == END
+    }
+
+    pub fn is_long_name(&self) -> bool {
+        self.attr() & 0x3f == ATTR_LONG_NAME
+    }
+
+    pub fn is_volume_label(&self) -> bool {
+        !self.is_long_name() && self.attr() & ATTR_VOLUME_ID != 0
+    }
+
+    pub fn attr(&self) -> u8 {
+        self.raw[11]
+    }
+
+    pub fn set_attr(&mut self, attr: u8) {
+        self.raw[11] = attr;
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attr() & ATTR_DIRECTORY != 0
+    }
+
+    /// Name as stored, 8 bytes for the base and 3 for the extension.
+    pub fn short_name(&self) -> [u8; 11] {
+        let mut name = [0; 11];
+        name.copy_from_slice(&self.raw[..11]);
+        if name[0] == KANJI_E5 {
+            name[0] = DELETED;
+        }
+        name
+    }
+
+    pub fn set_short_name(&mut self, name: &[u8; 11], case: u8) {
+        self.raw[..11].copy_from_slice(name);
+        if self.raw[0] == DELETED {
+            self.raw[0] = KANJI_E5;
+        }
+        self.raw[12] = case;
+    }
+
+    /// Short name displayed as `BASE.EXT`, in lower case if flagged.
+    pub fn display_name(&self) -> String {
+        let name = self.short_name();
+        let case = self.raw[12];
+        let mut s = String::new();
+        let part = |bytes: &[u8], lower: bool, s: &mut String| {
+            let len = bytes.iter().rposition(|b| *b != b' ').map_or(0, |i| i + 1);
+            for b in &bytes[..len] {
+                let c = match lower {
+                    true => b.to_ascii_lowercase(),
+                    false => *b,
+                };
+                // Bytes out of ASCII are taken as Latin-1 for lack of
+                // code pages.
+                s.push(c as char);
+            }
+        };
+        part(&name[..8], case & LOWER_BASE != 0, &mut s);
+        if name[8..].iter().any(|b| *b != b' ') {
+            s.push('.');
+            part(&name[8..], case & LOWER_EXT != 0, &mut s);
+        }
+        s
+    }
+
+    /// First cluster of the data, 0 for an empty file.
+    ///
+    /// The high word is only set on FAT32, it's 0 on the other types.
+    pub fn first_cluster(&self) -> u32 {
+        (read_u16(&self.raw, 20) as u32) << 16 | read_u16(&self.raw, 26) as u32
+    }
+
+    pub fn set_first_cluster(&mut self, cluster: u32) {
+        write_u16(&mut self.raw, 20, (cluster >> 16) as u16);
+        write_u16(&mut self.raw, 26, cluster as u16);
+    }
+
+    pub fn size(&self) -> u32 {
+        read_u32(&self.raw, 28)
+    }
+
+    pub fn set_size(&mut self, size: u32) {
+        write_u32(&mut self.raw, 28, size);
+    }
+}
+
+/// Checksum of a short name as stored, kept in its long name entries.
+fn checksum(name: &[u8]) -> u8 {
+    name.iter()
+        .fold(0u8, |sum, b| sum.rotate_right(1).wrapping_add(*b))
+}
+
+/// Long name being read from its entries, which come in reverse order.
+pub struct LongName {
+    units: Vec<u16>,
+    checksum: u8,
+    /// Order of the entry expected next, 0 when complete.
+    next: u8,
+}
+
+impl LongName {
+    pub fn new() -> Self {
+        Self {
+            units: Vec::new(),
+            checksum: 0,
+            next: 0,
+        }
+    }
+
+    /// Add a long name entry, an entry out of sequence drops the name.
+    pub fn push(&mut self, entry: &RawEntry) {
+        let order = entry.raw[0] & !LAST_LONG_ENTRY;
+        if entry.raw[0] & LAST_LONG_ENTRY != 0 {
+            self.units = vec![0xffff; order as usize * LONG_NAME_CHARS];
+            self.checksum = entry.raw[13];
+        } else if order != self.next || order == 0 || entry.raw[13] != self.checksum {
+            self.clear();
+            return;
+        }
+        if order == 0 {
+            self.clear();
+            return;
+        }
+        let start = (order as usize - 1) * LONG_NAME_CHARS;
+        for (i, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+            self.units[start + i] = read_u16(&entry.raw, *offset);
+        }
+        self.next = order - 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.units.clear();
+        self.next = 0;
+    }
+
+    /// Return the name if it's complete and belongs to the short entry,
+    /// and start a new one.
+    pub fn take(&mut self, entry: &RawEntry) -> Option<String> {
+        let complete = !self.units.is_empty() && self.next == 0;
+        let name = if complete && self.checksum == checksum(&entry.raw[..11]) {
+            let len = self
+                .units
+                .iter()
+                .position(|u| *u == 0)
+                .unwrap_or(self.units.len());
+            Some(
+                char::decode_utf16(self.units[..len].iter().copied())
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect(),
+            )
+        } else {
+            None
+        };
+        self.clear();
+        name
+    }
+}
+
+/// Check that name can be a long name.
+pub fn check_name(name: &str) -> Result<(), Error> {
+    if name.encode_utf16().count() > NAME_MAX {
+        return Err(Error::NameTooLong);
+    }
+    let invalid = |c: char| (c as u32) < 0x20 || "\"*/:<>?\\|".contains(c);
+    // Windows drops the trailing dots and spaces, the name wouldn't match.
+    if name.is_empty() || name.chars().any(invalid) || name.ends_with('.') || name.ends_with(' ') {
+        return Err(Error::InvalidArgument);
+    }
+    Ok(())
+}
+
+/// Long name entries of name in their order on the disk.
+fn long_entries(name: &str, checksum: u8) -> Vec<RawEntry> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    if !units.len().is_multiple_of(LONG_NAME_CHARS) {
+        units.push(0);
+    }
+    while !units.len().is_multiple_of(LONG_NAME_CHARS) {
+        units.push(0xffff);
+    }
+    let count = units.len() / LONG_NAME_CHARS;
+    (0..count)
+        .rev()
+        .map(|i| {
+            let mut entry = RawEntry {
+                raw: [0; ENTRY_SIZE],
+            };
+            entry.raw[0] = (i + 1) as u8;
+            if i == count - 1 {
+                entry.raw[0] |= LAST_LONG_ENTRY;
+            }
+            entry.raw[11] = ATTR_LONG_NAME;
+            entry.raw[13] = checksum;
+            for (j, offset) in LONG_NAME_OFFSETS.iter().enumerate() {
+                write_u16(&mut entry.raw, *offset, units[i * LONG_NAME_CHARS + j]);
+            }
+            entry
+        })
+        .collect()
+}
+
+fn is_short_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// Return the short name of name with its case flags, and false if it's
+/// only a basis to be numbered because the name doesn't fit.
+fn short_name(name: &str) -> ([u8; 11], u8, bool) {
+    let (base, ext) = match name.rfind('.') {
+        Some(dot) if dot > 0 => (&name[..dot], &name[dot + 1..]),
+        _ => (name, ""),
+    };
+    let mut short = [b' '; 11];
+    let mut exact = base.len() <= 8 && ext.len() <= 3;
+    let mut case = 0;
+    for (part, flag) in [(base, LOWER_BASE), (ext, LOWER_EXT)].iter() {
+        let lower = part.chars().any(|c| c.is_ascii_lowercase());
+        let upper = part.chars().any(|c| c.is_ascii_uppercase());
+        match (lower, upper) {
+            (true, true) => exact = false,
+            (true, false) => case |= flag,
+            _ => {}
+        }
+    }
+    let fill = |part: &str, out: &mut [u8], exact: &mut bool| {
+        let mut len = 0;
+        for c in part.chars() {
+            let c = c.to_ascii_uppercase();
+            if c == ' ' || c == '.' {
+                *exact = false;
+                continue;
+            }
+            if len == out.len() {
+                break;
+            }
+            out[len] = match is_short_char(c) {
+                true => c as u8,
+                false => {
+                    *exact = false;
+                    b'_'
+                }
+            };
+            len += 1;
+        }
+        len
+    };
+    let base_len = fill(base, &mut short[..8], &mut exact);
+    fill(ext, &mut short[8..], &mut exact);
+    if base_len == 0 {
+        exact = false;
+    }
+    match exact {
+        true => (short, case, true),
+        false => (short, 0, false),
+    }
+}
+
+/// Number the basis of a short name with `~n`.
+fn numbered(basis: &[u8; 11], n: u32) -> [u8; 11] {
+    let mut tail = [0; 8];
+    let mut digits = 0;
+    let mut n = n;
+    while n > 0 {
+        tail[7 - digits] = b'0' + (n % 10) as u8;
+        digits += 1;
+        n /= 10;
+    }
+    tail[7 - digits] = b'~';
+    let tail = &tail[7 - digits..];
+    let len = basis[..8]
+        .iter()
+        .position(|b| *b == b' ')
+        .unwrap_or(8)
+        .min(8 - tail.len());
+    let mut name = *basis;
+    name[len..len + tail.len()].copy_from_slice(tail);
+    for b in &mut name[len + tail.len()..8] {
+        *b = b' ';
+    }
+    name
+}
+
+/// Where the entries of a directory are.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Location {
+    /// Root directory of FAT12 and FAT16, before the data clusters.
+    FixedRoot,
+    Chain(u32),
+}
+
+/// An entry found in a directory.
+pub struct Found {
+    pub name: String,
+    pub entry: RawEntry,
+    /// Positions on the device of the long name entries, then of the
+    /// short one.
+    pub slots: Vec<u64>,
+    /// Index of the slot after the entry in the directory.
+    pub next: u64,
+}
+
+impl Found {
+    /// Position of the short entry.
+    pub fn position(&self) -> u64 {
+        *self.slots.last().unwrap()
+    }
+
+    pub fn is_dot(&self) -> bool {
+        let name = self.entry.short_name();
+        name == DOT || name == DOT_DOT
+    }
+}
+
+impl Volume {
+    /// Call f with the index, position and content of the slots of a
+    /// directory from start, until it breaks.
+    fn scan<T, F>(&self, dir: Location, start: u64, mut f: F) -> Result<Option<T>, Error>
+    where
+        F: FnMut(u64, u64, &RawEntry) -> ControlFlow<T>,
+    {
+        let per_cluster = (self.cluster_size / ENTRY_SIZE) as u64;
+        let mut unit = start / per_cluster;
+        let mut cluster = match dir {
+            Location::FixedRoot => 0,
+            Location::Chain(first) => match self.cluster_at(first, unit)? {
+                Some(cluster) => cluster,
+                None => return Ok(None),
+            },
+        };
+        let mut buf = vec![0; self.cluster_size];
+        loop {
+            // The fixed root is read by pieces of the size of a cluster.
+            let (offset, len) = match dir {
+                Location::FixedRoot => {
+                    let offset = unit * self.cluster_size as u64;
+                    let size = self.root_entries as u64 * ENTRY_SIZE as u64;
+                    if offset >= size {
+                        return Ok(None);
+                    }
+                    let len = (size - offset).min(self.cluster_size as u64) as usize;
+                    buf = self.read(self.root_offset + offset, len)?;
+                    (self.root_offset + offset, len)
+                }
+                Location::Chain(_) => {
+                    self.read_cluster(cluster, &mut buf)?;
+                    (self.cluster_offset(cluster)?, self.cluster_size)
+                }
+            };
+            let base = unit * per_cluster;
+            for i in start.max(base) - base..(len / ENTRY_SIZE) as u64 {
+                let slot = i as usize * ENTRY_SIZE;
+                let entry = RawEntry::parse(&buf[slot..]);
+                if let ControlFlow::Break(result) = f(base + i, offset + slot as u64, &entry) {
+                    return Ok(Some(result));
+                }
+            }
+            unit += 1;
+            if let Location::Chain(_) = dir {
+                cluster = match self.next_cluster(cluster)? {
+                    Some(next) if unit <= self.cluster_count as u64 => next,
+                    Some(_) => return Err(Error::Corrupted),
+                    None => return Ok(None),
+                };
+            }
+        }
+    }
+
+    /// Call f with the entries of a directory from the slot start, with
+    /// their long names, until it returns a result.
+    pub(super) fn entries<T, F>(
+        &self,
+        dir: Location,
+        start: u64,
+        mut f: F,
+    ) -> Result<Option<T>, Error>
+    where
+        F: FnMut(Found) -> Option<T>,
+    {
+        let mut long_name = LongName::new();
+        let mut slots = Vec::new();
+        let result = self.scan(dir, start, |index, position, entry| {
+            if entry.is_end() {
+                return ControlFlow::Break(None);
+            }
+            if entry.raw[0] == DELETED || entry.is_volume_label() {
+                long_name.clear();
+                slots.clear();
+                return ControlFlow::Continue(());
+            }
+            if entry.is_long_name() {
+                if entry.raw[0] & LAST_LONG_ENTRY != 0 {
+                    slots.clear();
+                }
+                long_name.push(entry);
+                slots.push(position);
+                return ControlFlow::Continue(());
+            }
+            slots.push(position);
+            let found = Found {
+                name: long_name
+                    .take(entry)
+                    .unwrap_or_else(|| entry.display_name()),
+                entry: *entry,
+                slots: mem::take(&mut slots),
+                next: index + 1,
+            };
+            match f(found) {
+                Some(result) => ControlFlow::Break(Some(result)),
+                None => ControlFlow::Continue(()),
+            }
+        })?;
+        Ok(result.and_then(|result| result))
+    }
+
+    /// Return the entry of a directory named name, which is compared
+    /// without case to the long and the short names.
+    pub(super) fn find(&self, dir: Location, name: &str) -> Result<Option<Found>, Error> {
+        self.entries(dir, 0, |found| {
+            let matches = found.name.eq_ignore_ascii_case(name)
+                || found.entry.display_name().eq_ignore_ascii_case(name);
+            match matches && !found.is_dot() {
+                true => Some(found),
+                false => None,
+            }
+        })
+    }
+
+    /// Return true if the directory starting at cluster has no entries
+    /// but `.` and `..`.
+    pub(super) fn is_empty_directory(&self, cluster: u32) -> Result<bool, Error> {
+        if cluster == 0 {
+            return Err(Error::Corrupted);
+        }
+        let other = self.entries(Location::Chain(cluster), 0, |found| match found.is_dot() {
+            true => None,
+            false => Some(()),
+        })?;
+        Ok(other.is_none())
+    }
+
+    /// Add entry to a directory under name, with long name entries if it
+    /// needs them, and return the position of the short entry.
+    pub(super) fn add_entry(
+        &self,
+        dir: Location,
+        name: &str,
+        mut entry: RawEntry,
+    ) -> Result<u64, Error> {
+        let (basis, case, exact) = short_name(name);
+        let mut taken = Vec::new();
+        self.entries(dir, 0, |found| {
+            taken.push(found.entry.short_name());
+            None::<()>
+        })?;
+        let long = if exact && !taken.contains(&basis) {
+            entry.set_short_name(&basis, case);
+            Vec::new()
+        } else {
+            let short = (1..1_000_000)
+                .map(|n| numbered(&basis, n))
+                .find(|short| !taken.contains(short))
+                .ok_or(Error::NoSpace)?;
+            entry.set_short_name(&short, 0);
+            long_entries(name, checksum(&entry.raw[..11]))
+        };
+
+        // Find enough free slots in a row, or add clusters at the end.
+        let needed = long.len() + 1;
+        let mut run = Vec::new();
+        let mut count = 0;
+        self.scan(dir, 0, |index, position, slot| {
+            count = index + 1;
+            if !slot.is_free() {
+                run.clear();
+                return ControlFlow::Continue(());
+            }
+            run.push(position);
+            match run.len() == needed {
+                true => ControlFlow::Break(()),
+                false => ControlFlow::Continue(()),
+            }
+        })?;
+        if run.len() < needed {
+            let first = match dir {
+                Location::FixedRoot => return Err(Error::NoSpace),
+                Location::Chain(first) => first,
+            };
+            let per_cluster = (self.cluster_size / ENTRY_SIZE) as u64;
+            let mut last = self
+                .cluster_at(first, count / per_cluster - 1)?
+                .ok_or(Error::Corrupted)?;
+            while run.len() < needed {
+                count += per_cluster;
+                if count > DIR_ENTRIES_MAX {
+                    return Err(Error::NoSpace);
+                }
+                last = self.alloc_cluster(Some(last))?;
+                let offset = self.cluster_offset(last)?;
+                run.extend((0..per_cluster).map(|i| offset + i * ENTRY_SIZE as u64));
+            }
+        }
+        for (slot, long) in run.iter().zip(long.iter()) {
+            self.write(*slot, &long.raw)?;
+        }
+        self.write(run[needed - 1], &entry.raw)?;
+        Ok(run[needed - 1])
+    }
+
+    /// Mark the slots of an entry deleted.
+    pub(super) fn remove_entry(&self, slots: &[u64]) -> Result<(), Error> {
+        for slot in slots {
+            self.write(*slot, &[DELETED])?;
+        }
+        Ok(())
+    }
+
+    /// Point the `..` entry of the directory starting at cluster to the
+    /// one starting at parent, 0 for the root.
+    pub(super) fn set_parent(&self, cluster: u32, parent: u32) -> Result<(), Error> {
+        let mut buf = vec![0; self.cluster_size];
+        self.read_cluster(cluster, &mut buf)?;
+        for slot in buf.chunks_mut(ENTRY_SIZE) {
+            let mut entry = RawEntry::parse(slot);
+            if entry.short_name() == DOT_DOT {
+                entry.set_first_cluster(parent);
+                slot.copy_from_slice(&entry.raw);
+                return self.write_cluster(cluster, &buf);
+            }
+        }
+        Err(Error::Corrupted)
+    }
+}
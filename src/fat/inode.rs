@@ -0,0 +1,392 @@
+use alloc::{sync::Arc, vec};
+use core::any::Any;
+
+use super::{
+    bpb::FatType,
+    dir::{self, Location, RawEntry},
+    Volume,
+};
+use crate::vfs::{DirEntry, Error, FileType, Inode, InodeRef, Metadata};
+
+/// Inode number of the root directory, which has no entry.
+const ROOT_INO: u64 = 1;
+
+/// Largest size of a file.
+const FILE_MAX: u64 = 0xffff_ffff;
+
+/// Inode of a mounted FAT filesystem.
+///
+/// FAT has no inodes, a file is known by the position of its directory
+/// entry. A rename moves the entry, a file open then keeps the old one.
+pub struct FatInode {
+    volume: Arc<Volume>,
+    /// Position on the device of the short entry, None for the root.
+    entry: Option<u64>,
+}
+
+impl FatInode {
+    pub(super) fn root(volume: Arc<Volume>) -> Self {
+        Self {
+            volume,
+            entry: None,
+        }
+    }
+
+    fn new(volume: Arc<Volume>, position: u64) -> Self {
+        Self {
+            volume,
+            entry: Some(position),
+        }
+    }
+
+    fn read_entry(&self, position: u64) -> Result<RawEntry, Error> {
+        let raw = self.volume.read(position, dir::ENTRY_SIZE)?;
+        Ok(RawEntry::parse(&raw))
+    }
+
+    /// Return the entry of a file, the root directory has none.
+    fn file_entry(&self) -> Result<(u64, RawEntry), Error> {
+        let position = self.entry.ok_or(Error::IsDirectory)?;
+        let entry = self.read_entry(position)?;
+        if entry.is_directory() {
+            return Err(Error::IsDirectory);
+        }
+        Ok((position, entry))
+    }
+
+    /// Return where the entries of this directory are.
+    fn location(&self) -> Result<Location, Error> {
+        let position = match self.entry {
+            Some(position) => position,
+            None if self.volume.kind == FatType::Fat32 => {
+                return Ok(Location::Chain(self.volume.root_cluster))
+            }
+            None => return Ok(Location::FixedRoot),
+        };
+        let entry = self.read_entry(position)?;
+        match entry.first_cluster() {
+            _ if !entry.is_directory() => Err(Error::NotDirectory),
+            0 => Err(Error::Corrupted),
+            cluster => Ok(Location::Chain(cluster)),
+        }
+    }
+
+    /// Cluster of this directory in the `..` entries of its children, 0
+    /// for the root.
+    fn parent_cluster(&self) -> Result<u32, Error> {
+        match (self.entry, self.location()?) {
+            (Some(_), Location::Chain(cluster)) => Ok(cluster),
+            _ => Ok(0),
+        }
+    }
+
+    /// Read from offset of the data of a file.
+    fn read_data(&self, entry: &RawEntry, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let size = entry.size() as u64;
+        if offset >= size {
+            return Ok(0);
+        }
+        let cluster_size = self.volume.cluster_size as u64;
+        let len = buf.len().min((size - offset) as usize);
+        let mut data = vec![0; cluster_size as usize];
+        let mut done = 0;
+        while done < len {
+            let pos = offset + done as u64;
+            let within = (pos % cluster_size) as usize;
+            let count = (cluster_size as usize - within).min(len - done);
+            let cluster = self
+                .volume
+                .cluster_at(entry.first_cluster(), pos / cluster_size)?
+                .ok_or(Error::Corrupted)?;
+            self.volume.read_cluster(cluster, &mut data)?;
+            buf[done..done + count].copy_from_slice(&data[within..within + count]);
+            done += count;
+        }
+        Ok(len)
+    }
+
+    /// Return the cluster at index of the data, adding it at the end of
+    /// the chain if it's just after it.
+    fn cluster_for_write(&self, entry: &mut RawEntry, index: u64) -> Result<u32, Error> {
+        let first = match entry.first_cluster() {
+            0 if index == 0 => {
+                let cluster = self.volume.alloc_cluster(None)?;
+                entry.set_first_cluster(cluster);
+                return Ok(cluster);
+            }
+            0 => return Err(Error::Corrupted),
+            first => first,
+        };
+        if let Some(cluster) = self.volume.cluster_at(first, index)? {
+            return Ok(cluster);
+        }
+        let last = match index {
+            0 => None,
+            _ => self.volume.cluster_at(first, index - 1)?,
+        };
+        match last {
+            Some(last) => self.volume.alloc_cluster(Some(last)),
+            None => Err(Error::Corrupted),
+        }
+    }
+
+    /// Write buf at offset of the data, which must not be after its end,
+    /// and count the bytes written in done, which are kept after an error.
+    fn write_data(
+        &self,
+        entry: &mut RawEntry,
+        offset: u64,
+        buf: &[u8],
+        done: &mut usize,
+    ) -> Result<(), Error> {
+        let cluster_size = self.volume.cluster_size as u64;
+        let mut data = vec![0; cluster_size as usize];
+        while *done < buf.len() {
+            let pos = offset + *done as u64;
+            let within = (pos % cluster_size) as usize;
+            let count = (cluster_size as usize - within).min(buf.len() - *done);
+            let cluster = self.cluster_for_write(entry, pos / cluster_size)?;
+            if count < cluster_size as usize {
+                self.volume.read_cluster(cluster, &mut data)?;
+            }
+            data[within..within + count].copy_from_slice(&buf[*done..*done + count]);
+            self.volume.write_cluster(cluster, &data)?;
+            *done += count;
+            if pos + count as u64 > entry.size() as u64 {
+                entry.set_size((pos + count as u64) as u32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Extend the file with zeros up to size, as FAT has no holes.
+    fn fill_zeros(&self, entry: &mut RawEntry, size: u64) -> Result<(), Error> {
+        let zeros = vec![0; self.volume.cluster_size];
+        while (entry.size() as u64) < size {
+            let offset = entry.size() as u64;
+            let len = (size - offset).min(zeros.len() as u64) as usize;
+            self.write_data(entry, offset, &zeros[..len], &mut 0)?;
+        }
+        Ok(())
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let position = match self.entry {
+            Some(position) => position,
+            None => {
+                return Ok(Metadata {
+                    ino: ROOT_INO,
+                    kind: FileType::Directory,
+                    size: 0,
+                    links: 1,
+                    mode: 0o755,
+                })
+            }
+        };
+        let entry = self.read_entry(position)?;
+        let (kind, size, mode) = match entry.is_directory() {
+            true => (FileType::Directory, 0, 0o755),
+            false => (FileType::File, entry.size() as u64, 0o644),
+        };
+        Ok(Metadata {
+            ino: position / dir::ENTRY_SIZE as u64,
+            kind,
+            size,
+            links: 1,
+            mode: match entry.attr() & dir::ATTR_READ_ONLY {
+                0 => mode,
+                _ => mode & !0o222,
+            },
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let (_, entry) = self.file_entry()?;
+        self.read_data(&entry, offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Error> {
+        let (position, mut entry) = self.file_entry()?;
+        if offset >= FILE_MAX && !buf.is_empty() {
+            return Err(Error::NoSpace);
+        }
+        let buf = &buf[..buf.len().min((FILE_MAX - offset.min(FILE_MAX)) as usize)];
+        let mut done = 0;
+        let result = self
+            .fill_zeros(&mut entry, offset)
+            .and_then(|_| self.write_data(&mut entry, offset, buf, &mut done));
+        entry.set_attr(entry.attr() | dir::ATTR_ARCHIVE);
+        self.volume.write(position, &entry.raw)?;
+        match result {
+            Err(err) if done == 0 => Err(err),
+            _ => Ok(done),
+        }
+    }
+
+    fn truncate(&self, size: u64) -> Result<(), Error> {
+        let (position, mut entry) = self.file_entry()?;
+        if size > FILE_MAX {
+            return Err(Error::NoSpace);
+        }
+        let result = if size < entry.size() as u64 {
+            let cluster_size = self.volume.cluster_size as u64;
+            let keep = size.div_ceil(cluster_size);
+            let first = entry.first_cluster();
+            let result = match keep {
+                _ if first == 0 => Ok(()),
+                0 => self
+                    .volume
+                    .free_chain(first)
+                    .map(|_| entry.set_first_cluster(0)),
+                _ => match self.volume.cluster_at(first, keep - 1)? {
+                    Some(last) => self.volume.cut_chain(last),
+                    None => Err(Error::Corrupted),
+                },
+            };
+            entry.set_size(size as u32);
+            result
+        } else {
+            self.fill_zeros(&mut entry, size)
+        };
+        entry.set_attr(entry.attr() | dir::ATTR_ARCHIVE);
+        self.volume.write(position, &entry.raw)?;
+        result
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, Error> {
+        match self.volume.find(self.location()?, name)? {
+            Some(found) => Ok(Arc::new(FatInode::new(
+                self.volume.clone(),
+                found.position(),
+            ))),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn readdir(&self, offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let found =
+            self.volume
+                .entries(self.location()?, offset, |found| match found.is_dot() {
+                    true => None,
+                    false => Some(found),
+                })?;
+        Ok(found.map(|found| {
+            let entry = DirEntry {
+                ino: found.position() / dir::ENTRY_SIZE as u64,
+                kind: match found.entry.is_directory() {
+                    true => FileType::Directory,
+                    false => FileType::File,
+                },
+                name: found.name,
+            };
+            (entry, found.next)
+        }))
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> Result<InodeRef, Error> {
+        let location = self.location()?;
+        dir::check_name(name)?;
+        if self.volume.find(location, name)?.is_some() {
+            return Err(Error::Exists);
+        }
+        let blank = [b' '; 11];
+        let position = match kind {
+            FileType::File => {
+                let entry = RawEntry::new(&blank, 0, dir::ATTR_ARCHIVE);
+                self.volume.add_entry(location, name, entry)?
+            }
+            FileType::Directory => {
+                let cluster = self.volume.alloc_cluster(None)?;
+                let mut data = vec![0; self.volume.cluster_size];
+                let mut dot = RawEntry::new(&dir::DOT, 0, dir::ATTR_DIRECTORY);
+                dot.set_first_cluster(cluster);
+                data[..dir::ENTRY_SIZE].copy_from_slice(&dot.raw);
+                let mut dot_dot = RawEntry::new(&dir::DOT_DOT, 0, dir::ATTR_DIRECTORY);
+                dot_dot.set_first_cluster(self.parent_cluster()?);
+                data[dir::ENTRY_SIZE..2 * dir::ENTRY_SIZE].copy_from_slice(&dot_dot.raw);
+                let mut entry = RawEntry::new(&blank, 0, dir::ATTR_DIRECTORY);
+                entry.set_first_cluster(cluster);
+                let result = self
+                    .volume
+                    .write_cluster(cluster, &data)
+                    .and_then(|_| self.volume.add_entry(location, name, entry));
+                match result {
+                    Ok(position) => position,
+                    Err(err) => {
+                        let _ = self.volume.free_chain(cluster);
+                        return Err(err);
+                    }
+                }
+            }
+            _ => return Err(Error::NotSupported),
+        };
+        Ok(Arc::new(FatInode::new(self.volume.clone(), position)))
+    }
+
+    fn symlink(&self, _name: &str, _target: &str) -> Result<InodeRef, Error> {
+        Err(Error::NotSupported)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Error> {
+        let found = self
+            .volume
+            .find(self.location()?, name)?
+            .ok_or(Error::NotFound)?;
+        let first = found.entry.first_cluster();
+        if found.entry.is_directory() && !self.volume.is_empty_directory(first)? {
+            return Err(Error::NotEmpty);
+        }
+        self.volume.remove_entry(&found.slots)?;
+        match first {
+            0 => Ok(()),
+            first => self.volume.free_chain(first),
+        }
+    }
+
+    fn rename(&self, name: &str, new_dir: &InodeRef, new_name: &str) -> Result<(), Error> {
+        let new_dir = match new_dir.as_any().downcast_ref::<FatInode>() {
+            Some(dir) if Arc::ptr_eq(&dir.volume, &self.volume) => dir,
+            _ => return Err(Error::CrossDevice),
+        };
+        dir::check_name(new_name)?;
+        let from = self.location()?;
+        let to = new_dir.location()?;
+        if from == to && name == new_name {
+            return Ok(());
+        }
+        let source = self.volume.find(from, name)?.ok_or(Error::NotFound)?;
+        let directory = source.entry.is_directory();
+        // The target may be the source itself when only the case changes.
+        if let Some(target) = self.volume.find(to, new_name)? {
+            if target.position() != source.position() {
+                let first = target.entry.first_cluster();
+                match (directory, target.entry.is_directory()) {
+                    (true, true) if !self.volume.is_empty_directory(first)? => {
+                        return Err(Error::NotEmpty)
+                    }
+                    (true, false) => return Err(Error::NotDirectory),
+                    (false, true) => return Err(Error::IsDirectory),
+                    _ => {}
+                }
+                self.volume.remove_entry(&target.slots)?;
+                if first != 0 {
+                    self.volume.free_chain(first)?;
+                }
+            }
+        }
+        self.volume.add_entry(to, new_name, source.entry)?;
+        self.volume.remove_entry(&source.slots)?;
+        if directory && from != to {
+            let parent = new_dir.parent_cluster()?;
+            self.volume
+                .set_parent(source.entry.first_cluster(), parent)?;
+        }
+        Ok(())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,140 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    block::{self, BlockDevice},
+    spinlock::Spinlock,
+    vfs::{Error, FileSystem, InodeRef},
+};
+
+mod bpb;
+mod dir;
+mod inode;
+mod table;
+
+use self::{
+    bpb::{Bpb, FatType},
+    inode::FatInode,
+};
+
+/// A FAT12, FAT16 or FAT32 filesystem on a block device.
+pub struct FatFs {
+    volume: Arc<Volume>,
+}
+
+/// State of a mounted FAT filesystem shared by its inodes.
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    kind: FatType,
+    /// Byte offsets and sizes on the device.
+    fat_offset: u64,
+    fat_size: u64,
+    fat_count: u32,
+    /// The FAT read and written if they aren't mirrored.
+    active_fat: Option<u32>,
+    /// Fixed root directory of FAT12 and FAT16.
+    root_offset: u64,
+    root_entries: u32,
+    /// Root directory of FAT32.
+    root_cluster: u32,
+    data_offset: u64,
+    cluster_size: usize,
+    cluster_count: u32,
+    /// FSInfo sector of FAT32, with the count of free clusters.
+    fsinfo_offset: Option<u64>,
+    /// Cluster from which the next free one is searched.
+    next_free: Spinlock<u32>,
+    /// Last cluster found in a chain, as (first, index, cluster), so that
+    /// the sequential accesses don't walk the chain from its start.
+    last_cluster: Spinlock<Option<(u32, u64, u32)>>,
+}
+
+impl Volume {
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(block::read_bytes(&*self.device, offset, len)?)
+    }
+
+    fn write(&self, offset: u64, data: &[u8]) -> Result<(), Error> {
+        Ok(block::write_bytes(&*self.device, offset, data)?)
+    }
+
+    /// Byte offset of a data cluster.
+    fn cluster_offset(&self, cluster: u32) -> Result<u64, Error> {
+        if cluster < 2 || cluster >= self.cluster_count + 2 {
+            return Err(Error::Corrupted);
+        }
+        Ok(self.data_offset + (cluster - 2) as u64 * self.cluster_size as u64)
+    }
+
+    /// Read a cluster, buf is one cluster long.
+    fn read_cluster(&self, cluster: u32, buf: &mut [u8]) -> Result<(), Error> {
+        let offset = self.cluster_offset(cluster)?;
+        let sector_size = self.device.sector_size() as u64;
+        self.device.read_sectors(offset / sector_size, buf)?;
+        Ok(())
+    }
+
+    fn write_cluster(&self, cluster: u32, buf: &[u8]) -> Result<(), Error> {
+        let offset = self.cluster_offset(cluster)?;
+        let sector_size = self.device.sector_size() as u64;
+        self.device.write_sectors(offset / sector_size, buf)?;
+        Ok(())
+    }
+}
+
+impl FatFs {
+    /// Read the boot sector of device.
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Self, Error> {
+        let raw = block::read_bytes(&*device, 0, bpb::BOOT_SECTOR_SIZE)?;
+        let bpb = Bpb::parse(&raw)?;
+        let sector_size = bpb.bytes_per_sector as u64;
+        let cluster_size = (bpb.bytes_per_sector * bpb.sectors_per_cluster) as usize;
+        if !sector_size.is_multiple_of(device.sector_size() as u64)
+            || bpb.total_sectors as u64 * sector_size
+                > device.sector_count() * device.sector_size() as u64
+        {
+            return Err(Error::Corrupted);
+        }
+        let kind = bpb.fat_type();
+        let fsinfo_offset = match kind {
+            FatType::Fat32
+                if bpb.fsinfo_sector != 0 && bpb.fsinfo_sector < bpb.reserved_sectors =>
+            {
+                Some(bpb.fsinfo_sector as u64 * sector_size)
+            }
+            _ => None,
+        };
+        Ok(Self {
+            volume: Arc::new(Volume {
+                device,
+                kind,
+                fat_offset: bpb.reserved_sectors as u64 * sector_size,
+                fat_size: bpb.fat_size as u64 * sector_size,
+                fat_count: bpb.fat_count,
+                active_fat: bpb.active_fat(),
+                root_offset: bpb.root_sector() as u64 * sector_size,
+                root_entries: bpb.root_entries,
+                root_cluster: bpb.root_cluster,
+                data_offset: bpb.data_sector() as u64 * sector_size,
+                cluster_size,
+                cluster_count: bpb.cluster_count(),
+                fsinfo_offset,
+                next_free: Spinlock::with_name(2, "FAT_NEXT_FREE"),
+                last_cluster: Spinlock::with_name(None, "FAT_LAST_CLUSTER"),
+            }),
+        })
+    }
+}
+
+impl FileSystem for FatFs {
+    fn name(&self) -> &'static str {
+        match self.volume.kind {
+            FatType::Fat12 => "fat12",
+            FatType::Fat16 => "fat16",
+            FatType::Fat32 => "fat32",
+        }
+    }
+
+    fn root(&self) -> Result<InodeRef, Error> {
+        Ok(Arc::new(FatInode::root(self.volume.clone())))
+    }
+}
@@ -0,0 +1,225 @@
+use alloc::vec;
+
+use super::{bpb::FatType, Volume};
+use crate::{
+    block::{read_u16, read_u32, write_u16, write_u32},
+    vfs::Error,
+};
+
+const FREE: u32 = 0;
+
+/// Entries read at once when searching a free cluster.
+const SCAN_ENTRIES: u32 = 1024;
+
+const FSINFO_LEAD_SIGNATURE: u32 = 0x4161_5252;
+const FSINFO_STRUCT_SIGNATURE: u32 = 0x6141_7272;
+/// Free count of the FSInfo sector when it's unknown.
+const FSINFO_UNKNOWN: u32 = 0xffff_ffff;
+
+impl Volume {
+    /// Byte offset of the entry of a cluster in a FAT.
+    fn entry_offset(&self, cluster: u32) -> u64 {
+        match self.kind {
+            FatType::Fat12 => cluster as u64 * 3 / 2,
+            FatType::Fat16 => cluster as u64 * 2,
+            FatType::Fat32 => cluster as u64 * 4,
+        }
+    }
+
+    /// Bytes to read for an entry.
+    fn entry_bytes(&self) -> usize {
+        match self.kind {
+            FatType::Fat12 | FatType::Fat16 => 2,
+            FatType::Fat32 => 4,
+        }
+    }
+
+    /// Decode the entry of cluster from the bytes at its offset.
+    fn decode(&self, bytes: &[u8], cluster: u32) -> u32 {
+        match self.kind {
+            FatType::Fat12 if cluster % 2 == 1 => read_u16(bytes, 0) as u32 >> 4,
+            FatType::Fat12 => read_u16(bytes, 0) as u32 & 0xfff,
+            FatType::Fat16 => read_u16(bytes, 0) as u32,
+            // The high 4 bits are reserved.
+            FatType::Fat32 => read_u32(bytes, 0) & 0x0fff_ffff,
+        }
+    }
+
+    /// Smallest value ending a chain.
+    fn end_of_chain(&self) -> u32 {
+        match self.kind {
+            FatType::Fat12 => 0xff8,
+            FatType::Fat16 => 0xfff8,
+            FatType::Fat32 => 0x0fff_fff8,
+        }
+    }
+
+    fn fat_start(&self, fat: u32) -> u64 {
+        self.fat_offset + fat as u64 * self.fat_size
+    }
+
+    fn fat_entry(&self, cluster: u32) -> Result<u32, Error> {
+        let fat = self.fat_start(self.active_fat.unwrap_or(0));
+        let bytes = self.read(fat + self.entry_offset(cluster), self.entry_bytes())?;
+        Ok(self.decode(&bytes, cluster))
+    }
+
+    /// Set the entry of cluster in every FAT, or in the active one.
+    fn set_fat_entry(&self, cluster: u32, value: u32) -> Result<(), Error> {
+        for fat in 0..self.fat_count {
+            if self.active_fat.is_some_and(|active| active != fat) {
+                continue;
+            }
+            let offset = self.fat_start(fat) + self.entry_offset(cluster);
+            let mut bytes = self.read(offset, self.entry_bytes())?;
+            match self.kind {
+                FatType::Fat12 => {
+                    let old = read_u16(&bytes, 0);
+                    let new = if cluster % 2 == 1 {
+                        old & 0x000f | (value as u16) << 4
+                    } else {
+                        old & 0xf000 | value as u16 & 0x0fff
+                    };
+                    write_u16(&mut bytes, 0, new);
+                }
+                FatType::Fat16 => write_u16(&mut bytes, 0, value as u16),
+                FatType::Fat32 => {
+                    let old = read_u32(&bytes, 0);
+                    write_u32(&mut bytes, 0, old & 0xf000_0000 | value & 0x0fff_ffff);
+                }
+            }
+            self.write(offset, &bytes)?;
+        }
+        Ok(())
+    }
+
+    /// Return the cluster after this one in its chain, None at the end.
+    pub(super) fn next_cluster(&self, cluster: u32) -> Result<Option<u32>, Error> {
+        let next = self.fat_entry(cluster)?;
+        if next >= self.end_of_chain() {
+            return Ok(None);
+        }
+        // Free, reserved and bad clusters can't be in a chain.
+        if next < 2 || next >= self.cluster_count + 2 {
+            return Err(Error::Corrupted);
+        }
+        Ok(Some(next))
+    }
+
+    /// Return the cluster at index of the chain starting at first, None
+    /// past its end.
+    pub(super) fn cluster_at(&self, first: u32, index: u64) -> Result<Option<u32>, Error> {
+        let (mut i, mut cluster) = match *self.last_cluster.lock() {
+            Some((start, i, cluster)) if start == first && i <= index => (i, cluster),
+            _ => (0, first),
+        };
+        if first == 0 {
+            return Ok(None);
+        }
+        while i < index {
+            cluster = match self.next_cluster(cluster)? {
+                Some(next) => next,
+                None => return Ok(None),
+            };
+            i += 1;
+            // A loop in the chain would never end.
+            if i > self.cluster_count as u64 {
+                return Err(Error::Corrupted);
+            }
+        }
+        *self.last_cluster.lock() = Some((first, index, cluster));
+        Ok(Some(cluster))
+    }
+
+    /// Allocate a zeroed cluster at the end of a chain, or as a new chain
+    /// if previous is None.
+    pub(super) fn alloc_cluster(&self, previous: Option<u32>) -> Result<u32, Error> {
+        let cluster = self.find_free()?;
+        self.write_cluster(cluster, &vec![0; self.cluster_size])?;
+        self.set_fat_entry(cluster, 0x0fff_ffff)?;
+        if let Some(previous) = previous {
+            self.set_fat_entry(previous, cluster)?;
+        }
+        *self.next_free.lock() = cluster + 1;
+        self.update_fsinfo(-1, cluster + 1)?;
+        Ok(cluster)
+    }
+
+    /// Return a free cluster, searching from the last one allocated.
+    fn find_free(&self) -> Result<u32, Error> {
+        let end = self.cluster_count + 2;
+        let mut cluster = match *self.next_free.lock() {
+            next if next >= 2 && next < end => next,
+            _ => 2,
+        };
+        let mut checked = 0;
+        while checked < self.cluster_count {
+            let last = (cluster - cluster % SCAN_ENTRIES + SCAN_ENTRIES).min(end);
+            let start = self.fat_start(self.active_fat.unwrap_or(0));
+            let first_offset = self.entry_offset(cluster);
+            let len = self.entry_offset(last - 1) - first_offset + self.entry_bytes() as u64;
+            let bytes = self.read(start + first_offset, len as usize)?;
+            for c in cluster..last {
+                let offset = (self.entry_offset(c) - first_offset) as usize;
+                if self.decode(&bytes[offset..], c) == FREE {
+                    return Ok(c);
+                }
+            }
+            checked += last - cluster;
+            cluster = if last == end { 2 } else { last };
+        }
+        Err(Error::NoSpace)
+    }
+
+    /// Free the clusters of a chain.
+    pub(super) fn free_chain(&self, first: u32) -> Result<(), Error> {
+        let mut cluster = first;
+        let mut freed = 0;
+        loop {
+            let next = self.next_cluster(cluster)?;
+            self.set_fat_entry(cluster, FREE)?;
+            freed += 1;
+            match next {
+                Some(next) if freed <= self.cluster_count => cluster = next,
+                Some(_) => return Err(Error::Corrupted),
+                None => break,
+            }
+        }
+        *self.last_cluster.lock() = None;
+        self.update_fsinfo(freed as i64, 0)
+    }
+
+    /// Make cluster the end of its chain, freeing the clusters after it.
+    pub(super) fn cut_chain(&self, cluster: u32) -> Result<(), Error> {
+        let next = self.next_cluster(cluster)?;
+        self.set_fat_entry(cluster, 0x0fff_ffff)?;
+        match next {
+            Some(next) => self.free_chain(next),
+            None => Ok(()),
+        }
+    }
+
+    /// Adjust the free count of the FSInfo sector if it's known, and set
+    /// its hint of the next free cluster if next isn't 0.
+    fn update_fsinfo(&self, delta: i64, next: u32) -> Result<(), Error> {
+        let offset = match self.fsinfo_offset {
+            Some(offset) => offset,
+            None => return Ok(()),
+        };
+        let mut sector = self.read(offset, 512)?;
+        if read_u32(&sector, 0) != FSINFO_LEAD_SIGNATURE
+            || read_u32(&sector, 484) != FSINFO_STRUCT_SIGNATURE
+        {
+            return Ok(());
+        }
+        let free = read_u32(&sector, 488);
+        if free != FSINFO_UNKNOWN {
+            let free = (free as i64 + delta).max(0).min(self.cluster_count as i64);
+            write_u32(&mut sector, 488, free as u32);
+        }
+        if next != 0 {
+            write_u32(&mut sector, 492, next);
+        }
+        self.write(offset, &sector)
+    }
+}
@@ -0,0 +1,23 @@
+use alloc::sync::Arc;
+
+use crate::{kprintln, multiboot, vfs};
+
+mod tar;
+
+pub use self::tar::TarFs;
+
+/// First word of the `module2` line of the archive in grub.cfg.
+const MODULE_NAME: &str = "initrd";
+
+/// Mount the tar archive loaded by the boot loader as the root filesystem.
+pub fn init() {
+    let module = match multiboot::find_module(MODULE_NAME) {
+        Some(module) => module,
+        None => return,
+    };
+    let result =
+        TarFs::parse(module.data()).and_then(|fs| vfs::mount(MODULE_NAME, "/", Arc::new(fs)));
+    if let Err(e) = result {
+        kprintln!("initrd: {}", e);
+    }
+}
@@ -0,0 +1,346 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{any::Any, str};
+
+use crate::vfs::{DirEntry, Error, FileSystem, FileType, Inode, InodeRef, Metadata};
+
+const BLOCK_SIZE: usize = 512;
+
+const NAME: (usize, usize) = (0, 100);
+const MODE: (usize, usize) = (100, 108);
+const SIZE: (usize, usize) = (124, 136);
+const CHECKSUM: (usize, usize) = (148, 156);
+const TYPEFLAG: usize = 156;
+const LINKNAME: (usize, usize) = (157, 257);
+const MAGIC: (usize, usize) = (257, 262);
+const PREFIX: (usize, usize) = (345, 500);
+
+const USTAR_MAGIC: &[u8] = b"ustar";
+
+const TYPE_FILE: [u8; 3] = [b'0', 0, b'7'];
+const TYPE_HARDLINK: u8 = b'1';
+const TYPE_SYMLINK: u8 = b'2';
+const TYPE_DIRECTORY: u8 = b'5';
+/// GNU extensions: the data is the name or link name of the next entry.
+const TYPE_GNU_LONGNAME: u8 = b'L';
+const TYPE_GNU_LONGLINK: u8 = b'K';
+
+const ROOT: usize = 0;
+
+/// A file, directory or symlink of the archive.
+struct Node {
+    name: String,
+    kind: FileType,
+    mode: u16,
+    /// Content of a file or target of a symlink, inside the archive.
+    data: &'static [u8],
+    children: Vec<usize>,
+}
+
+impl Node {
+    fn new(name: &str, kind: FileType, mode: u16, data: &'static [u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            kind,
+            mode,
+            data,
+            children: Vec::new(),
+        }
+    }
+}
+
+/// Read-only filesystem of a tar (ustar) archive in memory.
+///
+/// The files are not copied, their data point into the archive.
+pub struct TarFs {
+    nodes: Arc<Vec<Node>>,
+}
+
+/// Inode of a TarFs, the index of its node.
+struct TarInode {
+    nodes: Arc<Vec<Node>>,
+    index: usize,
+}
+
+/// Return the field until the first null byte.
+fn field(header: &'static [u8], (start, end): (usize, usize)) -> &'static [u8] {
+    let field = &header[start..end];
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+/// Parse an octal number padded with spaces or null bytes.
+fn parse_octal(field: &[u8]) -> Option<u64> {
+    let mut value: u64 = 0;
+    let mut digits = field
+        .iter()
+        .skip_while(|b| **b == b' ')
+        .take_while(|b| **b != b' ' && **b != 0)
+        .peekable();
+    digits.peek()?;
+    for b in digits {
+        match b {
+            b'0'..=b'7' => value = value.checked_mul(8)? + (b - b'0') as u64,
+            _ => return None,
+        }
+    }
+    Some(value)
+}
+
+/// Check the sum of the header bytes, the checksum field counted as
+/// spaces.
+fn checksum_ok(header: &[u8]) -> bool {
+    let sum: u64 = header
+        .iter()
+        .enumerate()
+        .map(|(i, b)| {
+            if i >= CHECKSUM.0 && i < CHECKSUM.1 {
+                b' ' as u64
+            } else {
+                *b as u64
+            }
+        })
+        .sum();
+    parse_octal(&header[CHECKSUM.0..CHECKSUM.1]) == Some(sum)
+}
+
+fn to_str(bytes: &'static [u8]) -> Result<&'static str, Error> {
+    str::from_utf8(bytes).map_err(|_| Error::Corrupted)
+}
+
+impl TarFs {
+    /// Build the tree of the archive.
+    pub fn parse(archive: &'static [u8]) -> Result<Self, Error> {
+        let mut nodes = Vec::new();
+        nodes.push(Node::new("", FileType::Directory, 0o755, &[]));
+        let mut long_name: Option<&'static [u8]> = None;
+        let mut long_link: Option<&'static [u8]> = None;
+        let mut offset = 0;
+        while offset + BLOCK_SIZE <= archive.len() {
+            let header = &archive[offset..offset + BLOCK_SIZE];
+            if header.iter().all(|b| *b == 0) {
+                break;
+            }
+            if !checksum_ok(header) {
+                return Err(Error::Corrupted);
+            }
+            let size = parse_octal(&header[SIZE.0..SIZE.1]).ok_or(Error::Corrupted)? as usize;
+            let start = offset + BLOCK_SIZE;
+            let data = match start.checked_add(size) {
+                Some(end) if end <= archive.len() => &archive[start..end],
+                _ => return Err(Error::Corrupted),
+            };
+            offset = start + (size + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+
+            let kind = header[TYPEFLAG];
+            if kind == TYPE_GNU_LONGNAME || kind == TYPE_GNU_LONGLINK {
+                let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+                if kind == TYPE_GNU_LONGNAME {
+                    long_name = Some(&data[..len]);
+                } else {
+                    long_link = Some(&data[..len]);
+                }
+                continue;
+            }
+            let mut path = String::new();
+            match long_name.take() {
+                Some(name) => path.push_str(to_str(name)?),
+                None => {
+                    let prefix = field(header, PREFIX);
+                    if header[MAGIC.0..MAGIC.1] == *USTAR_MAGIC && !prefix.is_empty() {
+                        path.push_str(to_str(prefix)?);
+                        path.push('/');
+                    }
+                    path.push_str(to_str(field(header, NAME))?);
+                }
+            }
+            let link = match long_link.take() {
+                Some(link) => link,
+                None => field(header, LINKNAME),
+            };
+            let mode = parse_octal(&header[MODE.0..MODE.1]).unwrap_or(0) as u16 & 0o7777;
+            match kind {
+                _ if TYPE_FILE.contains(&kind) => {
+                    insert(&mut nodes, &path, FileType::File, mode, data)
+                }
+                TYPE_DIRECTORY => insert(&mut nodes, &path, FileType::Directory, mode, &[]),
+                TYPE_SYMLINK => insert(&mut nodes, &path, FileType::Symlink, 0o777, link),
+                TYPE_HARDLINK => {
+                    let data = match find(&nodes, to_str(link)?) {
+                        Some(target) if nodes[target].kind == FileType::File => nodes[target].data,
+                        _ => return Err(Error::Corrupted),
+                    };
+                    insert(&mut nodes, &path, FileType::File, mode, data)
+                }
+                // Devices, fifos and pax headers are ignored.
+                _ => {}
+            }
+        }
+        Ok(Self {
+            nodes: Arc::new(nodes),
+        })
+    }
+}
+
+/// Return the node at path.
+fn find(nodes: &[Node], path: &str) -> Option<usize> {
+    let mut index = ROOT;
+    for name in path.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        index = *nodes[index]
+            .children
+            .iter()
+            .find(|child| nodes[**child].name == name)?;
+    }
+    Some(index)
+}
+
+/// Add the node at path, creating the missing directories above it.
+///
+/// An existing entry is replaced, as a later member of an archive
+/// overrides an earlier one. Paths going up with `..` are ignored.
+fn insert(nodes: &mut Vec<Node>, path: &str, kind: FileType, mode: u16, data: &'static [u8]) {
+    if path.split('/').any(|c| c == "..") {
+        return;
+    }
+    let mut index = ROOT;
+    let mut names = path
+        .split('/')
+        .filter(|c| !c.is_empty() && *c != ".")
+        .peekable();
+    while let Some(name) = names.next() {
+        let last = names.peek().is_none();
+        let child = nodes[index]
+            .children
+            .iter()
+            .copied()
+            .find(|child| nodes[*child].name == name);
+        index = match child {
+            Some(child) if !last && nodes[child].kind != FileType::Directory => {
+                // A file in the way of a directory, like tar replace it.
+                nodes[child] = Node::new(name, FileType::Directory, 0o755, &[]);
+                child
+            }
+            Some(child) => child,
+            None => {
+                let (node_kind, node_mode) = if last {
+                    (kind, mode)
+                } else {
+                    (FileType::Directory, 0o755)
+                };
+                nodes.push(Node::new(name, node_kind, node_mode, data));
+                let child = nodes.len() - 1;
+                nodes[index].children.push(child);
+                child
+            }
+        };
+    }
+    if index == ROOT && kind != FileType::Directory {
+        return;
+    }
+    let node = &mut nodes[index];
+    node.mode = mode;
+    if kind != FileType::Directory || node.kind != FileType::Directory {
+        node.kind = kind;
+        node.data = data;
+        node.children.clear();
+    }
+}
+
+impl FileSystem for TarFs {
+    fn name(&self) -> &'static str {
+        "tar"
+    }
+
+    fn root(&self) -> Result<InodeRef, Error> {
+        Ok(Arc::new(TarInode {
+            nodes: self.nodes.clone(),
+            index: ROOT,
+        }))
+    }
+}
+
+impl TarInode {
+    fn node(&self) -> &Node {
+        &self.nodes[self.index]
+    }
+}
+
+impl Inode for TarInode {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let node = self.node();
+        let subdirs = node
+            .children
+            .iter()
+            .filter(|child| self.nodes[**child].kind == FileType::Directory)
+            .count();
+        Ok(Metadata {
+            ino: self.index as u64 + 1,
+            kind: node.kind,
+            size: node.data.len() as u64,
+            links: match node.kind {
+                FileType::Directory => 2 + subdirs as u32,
+                _ => 1,
+            },
+            mode: node.mode,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let node = self.node();
+        if node.kind == FileType::Directory {
+            return Err(Error::IsDirectory);
+        }
+        if offset >= node.data.len() as u64 {
+            return Ok(0);
+        }
+        let data = &node.data[offset as usize..];
+        let count = buf.len().min(data.len());
+        buf[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, Error> {
+        let node = self.node();
+        if node.kind != FileType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        match node.children.iter().find(|c| self.nodes[**c].name == name) {
+            Some(index) => Ok(Arc::new(TarInode {
+                nodes: self.nodes.clone(),
+                index: *index,
+            })),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn readdir(&self, offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let node = self.node();
+        if node.kind != FileType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        Ok(node.children.get(offset as usize).map(|index| {
+            let child = &self.nodes[*index];
+            let entry = DirEntry {
+                name: child.name.clone(),
+                ino: *index as u64 + 1,
+                kind: child.kind,
+            };
+            (entry, offset + 1)
+        }))
+    }
+
+    fn readlink(&self) -> Result<String, Error> {
+        let node = self.node();
+        if node.kind != FileType::Symlink {
+            return Err(Error::InvalidArgument);
+        }
+        to_str(node.data).map(|target| target.to_string())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
@@ -0,0 +1,406 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::char;
+
+use super::{Volume, SECTOR_SIZE};
+use crate::{
+    block::read_u32,
+    vfs::{Error, FileType},
+};
+
+/// Inode number of the root directory, whose record is in the volume
+/// descriptor.
+pub const ROOT_INO: u64 = 1;
+
+/// Size of a record without its name.
+const RECORD_MIN: usize = 33;
+
+const FLAG_DIRECTORY: u8 = 0x02;
+/// The file goes on in the extent of the next record.
+const FLAG_MULTI_EXTENT: u8 = 0x80;
+
+// Rock Ridge flags of NM and of the components of SL
+const NM_CURRENT: u8 = 0x02;
+const NM_PARENT: u8 = 0x04;
+const SL_CONTINUE: u8 = 0x01;
+const SL_CURRENT: u8 = 0x02;
+const SL_PARENT: u8 = 0x04;
+const SL_ROOT: u8 = 0x08;
+
+/// Continuation areas of the system use entries followed at most.
+const CONTINUATIONS_MAX: usize = 16;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+/// Where the names of the directory tree are read.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Names {
+    /// The 8.3 names, without their version and in lowercase.
+    Plain,
+    /// Names of the supplementary tree in UCS-2 big endian.
+    Joliet,
+    /// NM entries in the system use areas, after the bytes to skip of
+    /// their SP entry.
+    RockRidge(usize),
+}
+
+/// A directory record.
+pub struct Record<'a> {
+    pub len: usize,
+    pub extent: u32,
+    pub size: u32,
+    pub flags: u8,
+    pub name: &'a [u8],
+    pub system_use: &'a [u8],
+}
+
+impl<'a> Record<'a> {
+    /// Parse the record at the start of raw, None if it doesn't fit.
+    ///
+    /// The numbers are both-endian, their little endian half is read.
+    pub fn parse(raw: &'a [u8]) -> Option<Self> {
+        let len = *raw.first()? as usize;
+        if len < RECORD_MIN + 1 || len > raw.len() {
+            return None;
+        }
+        let name_len = raw[32] as usize;
+        // The name is padded to an even offset.
+        let system_use = RECORD_MIN + name_len + (name_len + 1) % 2;
+        if RECORD_MIN + name_len > len {
+            return None;
+        }
+        Some(Self {
+            len,
+            // Skip the extended attribute record.
+            extent: read_u32(raw, 2).checked_add(raw[1] as u32)?,
+            size: read_u32(raw, 10),
+            flags: raw[25],
+            name: &raw[RECORD_MIN..RECORD_MIN + name_len],
+            system_use: &raw[system_use.min(len)..len],
+        })
+    }
+
+    /// Return true for the `.` and `..` records.
+    fn is_dot(&self) -> bool {
+        self.name == [0] || self.name == [1]
+    }
+}
+
+/// A file, directory or symlink, from its record and its Rock Ridge
+/// entries.
+#[derive(Debug, Clone)]
+pub struct Node {
+    pub ino: u64,
+    pub kind: FileType,
+    /// First block of the data.
+    pub extent: u32,
+    pub size: u64,
+    pub mode: u16,
+    pub links: u32,
+    /// Target of a symlink.
+    pub target: String,
+}
+
+impl Node {
+    pub fn root(record: &Record) -> Self {
+        Self {
+            ino: ROOT_INO,
+            kind: FileType::Directory,
+            extent: record.extent,
+            size: record.size as u64,
+            mode: 0o555,
+            links: 1,
+            target: String::new(),
+        }
+    }
+}
+
+/// An entry of a directory.
+pub struct Entry {
+    pub name: String,
+    pub node: Node,
+    /// Position of the next record in the directory.
+    pub next: u64,
+}
+
+/// Target of a symlink, built from the components of the SL entries.
+#[derive(Default)]
+struct Target {
+    absolute: bool,
+    components: Vec<Vec<u8>>,
+    /// The last component goes on in the next one.
+    continued: bool,
+}
+
+impl Target {
+    fn push(&mut self, records: &[u8]) {
+        let mut pos = 0;
+        while pos + 2 <= records.len() {
+            let flags = records[pos];
+            let content = match records.get(pos + 2..pos + 2 + records[pos + 1] as usize) {
+                Some(content) => content,
+                None => break,
+            };
+            pos += 2 + content.len();
+            let text: &[u8] = match flags {
+                _ if flags & SL_CURRENT != 0 => b".",
+                _ if flags & SL_PARENT != 0 => b"..",
+                _ if flags & SL_ROOT != 0 => {
+                    self.absolute |= self.components.is_empty();
+                    self.continued = false;
+                    continue;
+                }
+                _ => content,
+            };
+            match self.components.last_mut() {
+                Some(last) if self.continued => last.extend_from_slice(text),
+                _ => self.components.push(text.to_vec()),
+            }
+            self.continued = flags & SL_CONTINUE != 0;
+        }
+    }
+
+    fn build(&self) -> String {
+        let mut target = String::new();
+        if self.absolute {
+            target.push('/');
+        }
+        for (i, component) in self.components.iter().enumerate() {
+            if i != 0 {
+                target.push('/');
+            }
+            target.push_str(&String::from_utf8_lossy(component));
+        }
+        target
+    }
+}
+
+/// The Rock Ridge entries of a record.
+#[derive(Default)]
+struct RockRidge {
+    name: Option<Vec<u8>>,
+    mode: Option<u32>,
+    links: Option<u32>,
+    target: Option<Target>,
+    /// RE, the directory was moved here from too deep in the tree.
+    relocated: bool,
+    /// CL, the extent of the directory moved away from here.
+    child: Option<u32>,
+}
+
+/// Remove the version of a name, `;1`, and the dot of a name without
+/// extension.
+fn strip_version(name: &str) -> &str {
+    let name = match name.rfind(';') {
+        Some(i) if name[i + 1..].bytes().all(|b| b.is_ascii_digit()) => &name[..i],
+        _ => name,
+    };
+    match name.len() {
+        len if len > 1 && name.ends_with('.') => &name[..len - 1],
+        _ => name,
+    }
+}
+
+impl Volume {
+    /// Return the bytes to skip at the start of the system use areas if
+    /// the `.` record of the root directory has an SP entry, the mark of
+    /// Rock Ridge.
+    pub(super) fn rock_ridge(&self, root: &Node) -> Result<Option<usize>, Error> {
+        let raw = self.read(self.block_offset(root.extent), SECTOR_SIZE as usize)?;
+        let area = match Record::parse(&raw) {
+            Some(record) => record.system_use,
+            None => return Err(Error::Corrupted),
+        };
+        match area {
+            [b'S', b'P', len, _, 0xbe, 0xef, skip, ..] if *len >= 7 => Ok(Some(*skip as usize)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Read the system use entries of a record, following their
+    /// continuation areas.
+    fn system_use(&self, area: &[u8]) -> Result<RockRidge, Error> {
+        let mut rr = RockRidge::default();
+        let mut area = area.to_vec();
+        for _ in 0..CONTINUATIONS_MAX {
+            let mut continuation = None;
+            let mut pos = 0;
+            while pos + 4 <= area.len() {
+                let len = area[pos + 2] as usize;
+                if len < 4 || pos + len > area.len() {
+                    break;
+                }
+                let entry = &area[pos..pos + len];
+                match &entry[..2] {
+                    b"NM" if len >= 5 && entry[4] & (NM_CURRENT | NM_PARENT) == 0 => rr
+                        .name
+                        .get_or_insert_with(Vec::new)
+                        .extend_from_slice(&entry[5..]),
+                    b"PX" if len >= 20 => {
+                        rr.mode = Some(read_u32(entry, 4));
+                        rr.links = Some(read_u32(entry, 12));
+                    }
+                    b"SL" if len >= 5 => rr
+                        .target
+                        .get_or_insert_with(Target::default)
+                        .push(&entry[5..]),
+                    b"CE" if len >= 28 => {
+                        continuation =
+                            Some((read_u32(entry, 4), read_u32(entry, 12), read_u32(entry, 20)))
+                    }
+                    b"RE" => rr.relocated = true,
+                    b"CL" if len >= 12 => rr.child = Some(read_u32(entry, 4)),
+                    b"ST" => break,
+                    _ => {}
+                }
+                pos += len;
+            }
+            area = match continuation {
+                Some((block, offset, len)) if len as u64 <= SECTOR_SIZE => {
+                    self.read(self.block_offset(block) + offset as u64, len as usize)?
+                }
+                Some(_) => return Err(Error::Corrupted),
+                None => break,
+            };
+        }
+        Ok(rr)
+    }
+
+    /// Return the name of a record in the tree of the volume.
+    fn name(&self, record: &Record, rr: &RockRidge) -> String {
+        match (self.names, &rr.name) {
+            (Names::RockRidge(_), Some(name)) => String::from_utf8_lossy(name).into_owned(),
+            (Names::Joliet, _) => {
+                let units = record
+                    .name
+                    .chunks_exact(2)
+                    .map(|unit| u16::from_be_bytes([unit[0], unit[1]]));
+                let name: String = char::decode_utf16(units)
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect();
+                strip_version(&name).to_string()
+            }
+            _ => strip_version(&String::from_utf8_lossy(record.name)).to_lowercase(),
+        }
+    }
+
+    /// Build the node of a record at position on the device.
+    fn node(&self, record: &Record, rr: &RockRidge, position: u64) -> Result<Node, Error> {
+        let mut kind = match record.flags & FLAG_DIRECTORY {
+            0 => FileType::File,
+            _ => FileType::Directory,
+        };
+        let (mut extent, mut size) = (record.extent, record.size as u64);
+        if let Some(child) = rr.child {
+            // The size of the moved directory is in its `.` record.
+            let raw = self.read(self.block_offset(child), SECTOR_SIZE as usize)?;
+            let dot = Record::parse(&raw).ok_or(Error::Corrupted)?;
+            kind = FileType::Directory;
+            extent = dot.extent;
+            size = dot.size as u64;
+        }
+        let mut target = String::new();
+        match rr.mode.map(|mode| mode & S_IFMT) {
+            Some(S_IFLNK) | None if rr.target.is_some() => {
+                kind = FileType::Symlink;
+                target = rr.target.as_ref().map(Target::build).unwrap_or_default();
+                size = target.len() as u64;
+            }
+            Some(S_IFDIR) => kind = FileType::Directory,
+            _ => {}
+        }
+        let mode = match (rr.mode, kind) {
+            (Some(mode), _) => mode as u16 & 0o7777,
+            (None, FileType::Directory) => 0o555,
+            (None, FileType::Symlink) => 0o777,
+            (None, _) => 0o444,
+        };
+        Ok(Node {
+            ino: position,
+            kind,
+            extent,
+            size,
+            mode,
+            links: rr.links.unwrap_or(1),
+            target,
+        })
+    }
+
+    /// Call f on the entries of a directory from the position start,
+    /// without `.` and `..`, until it returns a value.
+    pub(super) fn entries<T, F>(&self, dir: &Node, start: u64, mut f: F) -> Result<Option<T>, Error>
+    where
+        F: FnMut(Entry) -> Option<T>,
+    {
+        let base = self.block_offset(dir.extent);
+        let mut loaded = None;
+        let mut raw = Vec::new();
+        // A file in several extents, with the block after the last one.
+        let mut pending: Option<(String, Node, u64)> = None;
+        let mut offset = start;
+        while offset < dir.size {
+            let start = offset - offset % SECTOR_SIZE;
+            if loaded != Some(start) {
+                let len = (dir.size - start).min(SECTOR_SIZE) as usize;
+                raw = self.read(base + start, len)?;
+                loaded = Some(start);
+            }
+            let within = (offset - start) as usize;
+            if raw[within] == 0 {
+                // The records don't cross sectors, the rest is padding.
+                offset = start + SECTOR_SIZE;
+                continue;
+            }
+            let record = Record::parse(&raw[within..]).ok_or(Error::Corrupted)?;
+            let position = base + offset;
+            offset += record.len as u64;
+            if record.is_dot() {
+                continue;
+            }
+            let rr = match self.names {
+                Names::RockRidge(skip) if skip <= record.system_use.len() => {
+                    self.system_use(&record.system_use[skip..])?
+                }
+                _ => RockRidge::default(),
+            };
+            if rr.relocated {
+                continue;
+            }
+            let (name, node) = match pending.take() {
+                // Only the contiguous extents are supported.
+                Some((name, mut node, end)) if record.extent as u64 == end => {
+                    node.size += record.size as u64;
+                    (name, node)
+                }
+                Some(_) => return Err(Error::Corrupted),
+                None => (self.name(&record, &rr), self.node(&record, &rr, position)?),
+            };
+            if record.flags & FLAG_MULTI_EXTENT != 0 {
+                let blocks = (record.size as u64).div_ceil(self.block_size);
+                pending = Some((name, node, record.extent as u64 + blocks));
+                continue;
+            }
+            let entry = Entry {
+                name,
+                node,
+                next: offset,
+            };
+            if let Some(result) = f(entry) {
+                return Ok(Some(result));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Return the entry of a directory named name.
+    pub(super) fn find(&self, dir: &Node, name: &str) -> Result<Option<Entry>, Error> {
+        self.entries(dir, 0, |entry| match entry.name == name {
+            true => Some(entry),
+            false => None,
+        })
+    }
+}
@@ -0,0 +1,92 @@
+use alloc::{string::String, sync::Arc};
+use core::any::Any;
+
+use super::{dir::Node, Volume};
+use crate::vfs::{DirEntry, Error, FileType, Inode, InodeRef, Metadata};
+
+/// Bytes read from the device at once.
+const READ_CHUNK: usize = 64 * 1024;
+
+/// Inode of a mounted ISO 9660 filesystem, its record is read at lookup
+/// as nothing changes.
+pub struct IsoInode {
+    volume: Arc<Volume>,
+    node: Node,
+}
+
+impl IsoInode {
+    pub(super) fn new(volume: Arc<Volume>, node: Node) -> Self {
+        Self { volume, node }
+    }
+
+    fn check_directory(&self) -> Result<(), Error> {
+        match self.node.kind {
+            FileType::Directory => Ok(()),
+            _ => Err(Error::NotDirectory),
+        }
+    }
+}
+
+impl Inode for IsoInode {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        Ok(Metadata {
+            ino: self.node.ino,
+            kind: self.node.kind,
+            size: self.node.size,
+            links: self.node.links,
+            mode: self.node.mode,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        match self.node.kind {
+            FileType::File => {}
+            FileType::Directory => return Err(Error::IsDirectory),
+            _ => return Err(Error::InvalidArgument),
+        }
+        if offset >= self.node.size {
+            return Ok(0);
+        }
+        let len = (buf.len() as u64).min(self.node.size - offset) as usize;
+        let start = self.volume.block_offset(self.node.extent) + offset;
+        for (i, part) in buf[..len].chunks_mut(READ_CHUNK).enumerate() {
+            let data = self
+                .volume
+                .read(start + (i * READ_CHUNK) as u64, part.len())?;
+            part.copy_from_slice(&data);
+        }
+        Ok(len)
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, Error> {
+        self.check_directory()?;
+        match self.volume.find(&self.node, name)? {
+            Some(entry) => Ok(Arc::new(IsoInode::new(self.volume.clone(), entry.node))),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn readdir(&self, offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        self.check_directory()?;
+        let entry = self.volume.entries(&self.node, offset, Some)?;
+        Ok(entry.map(|entry| {
+            let dir_entry = DirEntry {
+                name: entry.name,
+                ino: entry.node.ino,
+                kind: entry.node.kind,
+            };
+            (dir_entry, entry.next)
+        }))
+    }
+
+    fn readlink(&self) -> Result<String, Error> {
+        match self.node.kind {
+            FileType::Symlink => Ok(self.node.target.clone()),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
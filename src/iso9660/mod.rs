@@ -0,0 +1,126 @@
+use alloc::{sync::Arc, vec::Vec};
+
+use crate::{
+    block::{self, read_u16, BlockDevice},
+    vfs::{Error, FileSystem, InodeRef},
+};
+
+mod dir;
+mod inode;
+
+use self::{
+    dir::{Names, Node, Record},
+    inode::IsoInode,
+};
+
+/// Size of the sectors, in which the directory records never cross a
+/// boundary.
+const SECTOR_SIZE: u64 = 2048;
+
+/// Sector of the first volume descriptor, after the system area.
+const DESCRIPTORS_SECTOR: u64 = 16;
+/// Volume descriptors read at most before the terminator.
+const DESCRIPTORS_MAX: u64 = 64;
+
+const STANDARD_ID: &[u8] = b"CD001";
+
+const DESCRIPTOR_PRIMARY: u8 = 1;
+const DESCRIPTOR_SUPPLEMENTARY: u8 = 2;
+const DESCRIPTOR_TERMINATOR: u8 = 255;
+
+/// Escape sequences of a supplementary descriptor of Joliet, for the
+/// UCS-2 levels 1 to 3.
+const JOLIET_ESCAPES: [&[u8]; 3] = [b"%/@", b"%/C", b"%/E"];
+
+/// Offset of the root directory record in a volume descriptor.
+const ROOT_RECORD: usize = 156;
+
+/// A read-only ISO 9660 filesystem, the format of the CDs.
+///
+/// The names are read from the Rock Ridge entries if there are, or else
+/// from the Joliet tree, or else are the plain names in lowercase.
+pub struct IsoFs {
+    volume: Arc<Volume>,
+    root: Node,
+}
+
+/// State of a mounted ISO 9660 filesystem shared by its inodes.
+struct Volume {
+    device: Arc<dyn BlockDevice>,
+    /// Size of the blocks counted by the extents, 2048 in practice.
+    block_size: u64,
+    names: Names,
+}
+
+impl Volume {
+    fn read(&self, offset: u64, len: usize) -> Result<Vec<u8>, Error> {
+        Ok(block::read_bytes(&*self.device, offset, len)?)
+    }
+
+    /// Byte offset of a block.
+    fn block_offset(&self, block: u32) -> u64 {
+        block as u64 * self.block_size
+    }
+}
+
+impl IsoFs {
+    /// Read the volume descriptors of device and choose the directory
+    /// tree.
+    pub fn new(device: Arc<dyn BlockDevice>) -> Result<Self, Error> {
+        let mut primary = None;
+        let mut joliet = None;
+        for sector in DESCRIPTORS_SECTOR..DESCRIPTORS_SECTOR + DESCRIPTORS_MAX {
+            let raw = block::read_bytes(&*device, sector * SECTOR_SIZE, SECTOR_SIZE as usize)?;
+            if &raw[1..6] != STANDARD_ID {
+                return Err(Error::InvalidArgument);
+            }
+            match raw[0] {
+                DESCRIPTOR_PRIMARY if primary.is_none() => primary = Some(raw),
+                DESCRIPTOR_SUPPLEMENTARY
+                    if joliet.is_none()
+                        && JOLIET_ESCAPES.iter().any(|e| raw[88..88 + e.len()] == **e) =>
+                {
+                    joliet = Some(raw)
+                }
+                DESCRIPTOR_TERMINATOR => break,
+                _ => {}
+            }
+        }
+        let primary = primary.ok_or(Error::InvalidArgument)?;
+        let block_size = read_u16(&primary, 128) as u64;
+        if !block_size.is_power_of_two() || !(512..=SECTOR_SIZE).contains(&block_size) {
+            return Err(Error::Corrupted);
+        }
+        let mut volume = Volume {
+            device,
+            block_size,
+            names: Names::Plain,
+        };
+        let record = Record::parse(&primary[ROOT_RECORD..]).ok_or(Error::Corrupted)?;
+        let mut root = Node::root(&record);
+        if let Some(skip) = volume.rock_ridge(&root)? {
+            volume.names = Names::RockRidge(skip);
+        } else if let Some(joliet) = joliet {
+            let record = Record::parse(&joliet[ROOT_RECORD..]).ok_or(Error::Corrupted)?;
+            root = Node::root(&record);
+            volume.names = Names::Joliet;
+        }
+        Ok(Self {
+            volume: Arc::new(volume),
+            root,
+        })
+    }
+}
+
+impl FileSystem for IsoFs {
+    fn name(&self) -> &'static str {
+        "iso9660"
+    }
+
+    fn root(&self) -> Result<InodeRef, Error> {
+        Ok(Arc::new(IsoInode::new(
+            self.volume.clone(),
+            self.root.clone(),
+        )))
+    }
+}
@@ -1,6 +1,6 @@
 use core::marker::PhantomData;
 
-use crate::spinlock::Spinlock;
+use crate::{once::Lazy, spinlock::ConsoleLock};
 
 mod scancode;
 pub use self::scancode::ScancodeSet1;
@@ -10,20 +10,11 @@ pub use self::layout::Us104Key;
 
 // STATIC
 
-pub static mut KEYBOARD: Spinlock<Keyboard<Us104Key, ScancodeSet1>> = Spinlock::new(Keyboard {
-    decode_state: DecodeState::Start,
-    handle_ctrl: HandleCtrl::Ignore,
-    modifiers: Modifiers {
-        lshift: false,
-        rshift: false,
-        lctrl: false,
-        rctrl: false,
-        numlock: false,
-        capslock: false,
-        alt_gr: false,
-    },
-    _layout: PhantomData,
-    _set: PhantomData,
+pub static KEYBOARD: Lazy<ConsoleLock<Keyboard<Us104Key, ScancodeSet1>>> = Lazy::new(|| {
+    ConsoleLock::with_name(
+        Keyboard::new(Us104Key, ScancodeSet1, HandleCtrl::Ignore),
+        "KEYBOARD",
+    )
 });
 
 // STRUCT and ENUM
@@ -1,7 +1,18 @@
+use alloc::{format, sync::Arc, vec};
+use core::{arch::asm, convert::TryFrom, fmt, mem::size_of};
+
+use super::Pager;
 use crate::{
-    kprint, kprintln,
+    ata,
+    block::{self, BlockDevice},
+    ext2::Ext2Fs,
+    fat::FatFs,
+    iso9660::IsoFs,
+    kprint, kprintln, memory, partition,
     port::Port,
     screen_clear, screen_next, screen_prev, screen_setbgcolor, screen_setcolor, screen_setfgcolor,
+    tmpfs::{self, TmpFs},
+    vfs,
     vga_buffer::color::{Color, ColorCode},
 };
 
@@ -43,6 +54,29 @@ pub fn help() {
     kprintln!("info         - print information of the kernel");
     kprintln!("read_serial  - print all bytes in serial port");
     kprintln!("echo         - print on terminal all arguments");
+    kprintln!("hexdump      - hexdump <addr> [len], dump memory");
+    kprintln!("stack        - dump the kernel stack");
+    kprintln!("peek<N>      - peek<8|16|32> <addr>, read a value");
+    kprintln!("poke<N>      - poke<8|16|32> <addr> <value>, write a value");
+    kprintln!("vtop         - vtop <vaddr>, translate to physical address");
+    kprintln!("meminfo      - print heap usage");
+    kprintln!("leaks        - leaks [snap|on|off], list allocations since snap");
+    kprintln!("disks        - list the ATA disks and CD-ROM drives");
+    kprintln!("readsector   - readsector <disk> <lba>, dump a disk sector");
+    kprintln!("partitions   - list the partitions of the disks");
+    kprintln!("ls           - ls [path], list a directory");
+    kprintln!("cat          - cat <file>, print a file");
+    kprintln!("cd           - cd [dir], change the working directory");
+    kprintln!("pwd          - print the working directory");
+    kprintln!("mkdir        - mkdir <dir>..., create directories");
+    kprintln!("rm           - rm <path>..., remove files or empty directories");
+    kprintln!("mv           - mv <old> <new>, rename a file");
+    kprintln!("ln           - ln <target> <link>, create a symbolic link");
+    kprintln!("stat         - stat <path>, print the attributes of a file");
+    kprintln!("write        - write <file> [word]..., replace a file with a line");
+    kprintln!("truncate     - truncate <file> <size>, set the size of a file");
+    kprintln!("mount        - mount [<type> <source> <dir> [size]], list or mount");
+    kprintln!("umount       - umount <dir>, detach a filesystem");
     kprintln!("");
     kprintln!("shortcut:");
     kprintln!("  <Alt>+<ArrowLeft>  -> previous screen");
@@ -65,15 +99,12 @@ pub fn info() {
 /// Print from serial port.
 pub fn read_serial() {
     use core::str::from_utf8;
-    unsafe {
-        loop {
-            // TODO Remove interupt during lock to avoid dead lock.
-            let b = match crate::serial::SERIAL.lock().read_byte() {
-                Some(b) => b,
-                None => break,
-            };
-            kprint!("{}", from_utf8(&[b]).unwrap());
-        }
+    loop {
+        let b = match crate::serial::SERIAL.lock_irq().read_byte() {
+            Some(b) => b,
+            None => break,
+        };
+        kprint!("{}", from_utf8(&[b]).unwrap());
     }
     kprintln!();
 }
@@ -85,3 +116,608 @@ pub fn echo(args: &[&str]) {
         kprintln!("{}: '{}'", i, s);
     }
 }
+
+const HEXDUMP_DEFAULT_LEN: usize = 256;
+const HEXDUMP_LINE: usize = 16;
+
+/// Parse a decimal or hexadecimal (0x prefixed) number.
+fn parse_number(s: &str) -> Option<usize> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Print memory in hex and ascii columns.
+fn dump(addr: usize, len: usize) {
+    dump_bytes(
+        unsafe { core::slice::from_raw_parts(addr as *const u8, len) },
+        addr,
+    );
+}
+
+/// Print bytes in hex and ascii columns labelled from base, one screen at
+/// a time.
+fn dump_bytes(data: &[u8], base: usize) {
+    let mut pager = Pager::new();
+    for (line, bytes) in data.chunks(HEXDUMP_LINE).enumerate() {
+        kprint!("{:08x}  ", base + line * HEXDUMP_LINE);
+        for i in 0..HEXDUMP_LINE {
+            match bytes.get(i) {
+                Some(b) => kprint!("{:02x} ", b),
+                None => kprint!("   "),
+            }
+            if i == HEXDUMP_LINE / 2 - 1 {
+                kprint!(" ");
+            }
+        }
+        kprint!("|");
+        for b in bytes {
+            match b {
+                0x20..=0x7e => kprint!("{}", *b as char),
+                _ => kprint!("."),
+            }
+        }
+        kprintln!("|");
+        if !pager.next_line() {
+            break;
+        }
+    }
+}
+
+/// Dump memory: hexdump <addr> [len].
+pub fn hexdump(args: &[&str]) {
+    let (addr, len) = match args {
+        [addr] => (parse_number(addr), Some(HEXDUMP_DEFAULT_LEN)),
+        [addr, len] => (parse_number(addr), parse_number(len)),
+        _ => (None, None),
+    };
+    let (addr, len) = match (addr, len) {
+        (Some(addr), Some(len)) => (addr, len),
+        _ => {
+            kprintln!("usage: hexdump <addr> [len]");
+            return;
+        }
+    };
+    if !memory::is_mapped(addr, len) {
+        kprintln!("hexdump: {:#x}+{:#x} is not mapped", addr, len);
+        return;
+    }
+    dump(addr, len);
+}
+
+/// Dump the kernel stack from the current stack pointer to its top.
+pub fn stack() {
+    let esp: usize;
+    unsafe {
+        asm!("mov {}, esp", out(reg) esp, options(nomem, nostack, preserves_flags));
+    }
+    let top = crate::stack_top as *const () as usize;
+    let bottom = crate::stack_bottom as *const () as usize;
+    kprintln!(
+        "stack: bottom {:#010x} top {:#010x} esp {:#010x} ({} bytes used)",
+        bottom,
+        top,
+        esp,
+        top - esp
+    );
+    dump(esp, top - esp);
+}
+
+/// Check that a value of type T can be accessed at addr.
+fn check_access<T>(name: &str, addr: usize) -> bool {
+    if addr % size_of::<T>() != 0 {
+        kprintln!(
+            "{}: {:#x} is not aligned on {} bytes",
+            name,
+            addr,
+            size_of::<T>()
+        );
+        return false;
+    }
+    if !memory::is_mapped(addr, size_of::<T>()) {
+        kprintln!("{}: {:#x} is not mapped", name, addr);
+        return false;
+    }
+    true
+}
+
+/// Read a value: peek<8|16|32> <addr>.
+pub fn peek<T: Copy + fmt::LowerHex>(name: &str, args: &[&str]) {
+    let addr = match args {
+        [addr] => parse_number(addr),
+        _ => None,
+    };
+    let addr = match addr {
+        Some(addr) => addr,
+        None => {
+            kprintln!("usage: {} <addr>", name);
+            return;
+        }
+    };
+    if !check_access::<T>(name, addr) {
+        return;
+    }
+    let value = unsafe { (addr as *const T).read_volatile() };
+    kprintln!(
+        "{:#010x}: {:#0width$x}",
+        addr,
+        value,
+        width = size_of::<T>() * 2 + 2
+    );
+}
+
+/// Write a value: poke<8|16|32> <addr> <value>.
+pub fn poke<T: Copy + fmt::LowerHex + TryFrom<usize>>(name: &str, args: &[&str]) {
+    let (addr, value) = match args {
+        [addr, value] => (parse_number(addr), parse_number(value)),
+        _ => (None, None),
+    };
+    let (addr, value) = match (addr, value) {
+        (Some(addr), Some(value)) => (addr, value),
+        _ => {
+            kprintln!("usage: {} <addr> <value>", name);
+            return;
+        }
+    };
+    let value = match T::try_from(value) {
+        Ok(value) => value,
+        Err(_) => {
+            kprintln!(
+                "{}: {:#x} does not fit in {} bytes",
+                name,
+                value,
+                size_of::<T>()
+            );
+            return;
+        }
+    };
+    if !check_access::<T>(name, addr) {
+        return;
+    }
+    unsafe { (addr as *mut T).write_volatile(value) };
+}
+
+/// Translate a virtual address: vtop <vaddr>.
+pub fn vtop(args: &[&str]) {
+    let vaddr = match args {
+        [vaddr] => parse_number(vaddr),
+        _ => None,
+    };
+    let vaddr = match vaddr {
+        Some(vaddr) => vaddr,
+        None => {
+            kprintln!("usage: vtop <vaddr>");
+            return;
+        }
+    };
+    match memory::translate(vaddr) {
+        Some(paddr) => kprintln!("{:#010x} -> {:#010x}", vaddr, paddr),
+        None => kprintln!("vtop: {:#x} is not mapped", vaddr),
+    }
+}
+
+/// Print the heap usage.
+pub fn meminfo() {
+    let stats = memory::heap::HEAP.lock_irq().stats();
+    kprintln!("heap total:    {:>8} bytes", stats.total);
+    kprintln!("heap used:     {:>8} bytes", stats.used);
+    kprintln!("heap free:     {:>8} bytes", stats.free);
+    kprintln!("largest free:  {:>8} bytes", stats.largest_free);
+    kprintln!("free blocks:   {:>8}", stats.free_blocks);
+    kprintln!("fragmentation: {:>8}%", stats.fragmentation());
+    kprintln!("allocations:   {:>8}", stats.allocations);
+    kprintln!("deallocations: {:>8}", stats.deallocations);
+}
+
+/// List the allocations made since the last snapshot: leaks [snap|on|off].
+#[cfg(feature = "alloc_trace")]
+pub fn leaks(args: &[&str]) {
+    use core::sync::atomic::Ordering;
+    use memory::trace;
+
+    match args {
+        ["snap"] => trace::snapshot(),
+        ["on"] => trace::ENABLED.store(true, Ordering::Relaxed),
+        ["off"] => trace::ENABLED.store(false, Ordering::Relaxed),
+        [] if !cfg!(frame_pointers) => {
+            kprintln!("leaks: the kernel was built without frame pointers, the callers are unknown")
+        }
+        [] => {
+            let mut pager = Pager::new();
+            let mut more = true;
+            let mut count = 0;
+            let mut bytes = 0;
+            let dropped = trace::diff(|a| {
+                count += 1;
+                bytes += a.size;
+                if more {
+                    kprintln!(
+                        "{:#010x} {:>8} bytes from {:#010x}",
+                        a.ptr,
+                        a.size,
+                        a.caller
+                    );
+                    more = pager.next_line();
+                }
+            });
+            kprintln!("{} allocations, {} bytes", count, bytes);
+            if dropped != 0 {
+                kprintln!("{} allocations were not traced (table full)", dropped);
+            }
+        }
+        _ => kprintln!("usage: leaks [snap|on|off]"),
+    }
+}
+
+/// List the allocations made since the last snapshot: leaks [snap|on|off].
+#[cfg(not(feature = "alloc_trace"))]
+pub fn leaks(_args: &[&str]) {
+    kprintln!("leaks: allocation tracing is not built in (feature alloc_trace)");
+}
+
+/// List the ATA disks and CD-ROM drives.
+pub fn disks() {
+    for drive in ata::drives() {
+        let info = drive.info();
+        kprintln!(
+            "{}: {} sectors ({} MiB){} - {}",
+            drive.name(),
+            info.sectors,
+            info.sectors * block::SECTOR_SIZE as u64 / (1024 * 1024),
+            if info.lba48 { ", LBA48" } else { "" },
+            info.model()
+        );
+    }
+    for cdrom in ata::cdroms() {
+        match cdrom.sector_count() {
+            0 => kprintln!("{}: CD-ROM, no medium - {}", cdrom.name(), cdrom.model()),
+            sectors => kprintln!(
+                "{}: CD-ROM, {} sectors ({} MiB) - {}",
+                cdrom.name(),
+                sectors,
+                sectors * cdrom.sector_size() as u64 / (1024 * 1024),
+                cdrom.model()
+            ),
+        }
+    }
+}
+
+/// Dump a disk sector: readsector <disk> <lba>.
+pub fn readsector(args: &[&str]) {
+    let (name, lba) = match args {
+        [name, lba] => (*name, parse_number(lba)),
+        _ => ("", None),
+    };
+    let lba = match lba {
+        Some(lba) => lba as u64,
+        None => {
+            kprintln!("usage: readsector <disk> <lba>");
+            return;
+        }
+    };
+    let drive = match partition::find_device(name) {
+        Some(drive) => drive,
+        None => {
+            kprintln!("readsector: {}: no such disk", name);
+            return;
+        }
+    };
+    let mut sector = vec![0; drive.sector_size()];
+    match drive.read_sectors(lba, &mut sector) {
+        Ok(()) => dump_bytes(&sector, 0),
+        Err(e) => kprintln!("readsector: {}: {}", name, e),
+    }
+}
+
+/// List the partitions of the disks.
+pub fn partitions() {
+    let mut pager = Pager::new();
+    for p in partition::partitions() {
+        kprintln!(
+            "{:<6} {:<12} start {:>10} size {:>6} MiB {}",
+            p.name(),
+            p.kind(),
+            p.start(),
+            p.sector_count() * p.sector_size() as u64 / (1024 * 1024),
+            p.label()
+        );
+        if !pager.next_line() {
+            break;
+        }
+    }
+}
+
+/// Format the permission bits like `ls -l`.
+fn mode_string(mode: u16) -> [u8; 9] {
+    let mut s = *b"rwxrwxrwx";
+    for (i, c) in s.iter_mut().enumerate() {
+        if mode & (0o400 >> i) == 0 {
+            *c = b'-';
+        }
+    }
+    s
+}
+
+/// Print one line of `ls` for the file at path shown as name.
+fn print_file(path: &str, name: &str) {
+    let metadata = match vfs::lstat(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            kprintln!("ls: {}: {}", path, e);
+            return;
+        }
+    };
+    kprint!(
+        "{}{} {:>3} {:>9} {}",
+        metadata.kind.as_char(),
+        core::str::from_utf8(&mode_string(metadata.mode)).unwrap(),
+        metadata.links,
+        metadata.size,
+        name
+    );
+    if metadata.kind == vfs::FileType::Symlink {
+        if let Ok(target) = vfs::readlink(path) {
+            kprint!(" -> {}", target);
+        }
+    }
+    kprintln!();
+}
+
+/// List a directory: ls [path].
+pub fn ls(args: &[&str]) {
+    let path = args.first().copied().unwrap_or(".");
+    match vfs::stat(path) {
+        Ok(metadata) if metadata.kind != vfs::FileType::Directory => {
+            print_file(path, path);
+            return;
+        }
+        Ok(_) => {}
+        Err(e) => {
+            kprintln!("ls: {}: {}", path, e);
+            return;
+        }
+    }
+    let fd = match vfs::open(path, vfs::OpenFlags::READ) {
+        Ok(fd) => fd,
+        Err(e) => {
+            kprintln!("ls: {}: {}", path, e);
+            return;
+        }
+    };
+    let mut pager = Pager::new();
+    loop {
+        match vfs::readdir(fd) {
+            Ok(Some(entry)) => print_file(&format!("{}/{}", path, entry.name), &entry.name),
+            Ok(None) => break,
+            Err(e) => {
+                kprintln!("ls: {}: {}", path, e);
+                break;
+            }
+        }
+        if !pager.next_line() {
+            break;
+        }
+    }
+    let _ = vfs::close(fd);
+}
+
+/// Print a file: cat <file>.
+pub fn cat(args: &[&str]) {
+    let path = match args {
+        [path] => *path,
+        _ => {
+            kprintln!("usage: cat <file>");
+            return;
+        }
+    };
+    let fd = match vfs::open(path, vfs::OpenFlags::READ) {
+        Ok(fd) => fd,
+        Err(e) => {
+            kprintln!("cat: {}: {}", path, e);
+            return;
+        }
+    };
+    let mut pager = Pager::new();
+    let mut buf = [0; block::SECTOR_SIZE];
+    'file: loop {
+        let count = match vfs::read(fd, &mut buf) {
+            Ok(0) => break,
+            Ok(count) => count,
+            Err(e) => {
+                kprintln!("cat: {}: {}", path, e);
+                break;
+            }
+        };
+        for b in &buf[..count] {
+            kprint!("{}", *b as char);
+            if *b == b'\n' && !pager.next_line() {
+                break 'file;
+            }
+        }
+    }
+    let _ = vfs::close(fd);
+}
+
+/// Change the working directory: cd [dir].
+pub fn cd(args: &[&str]) {
+    let path = args.first().copied().unwrap_or("/");
+    if let Err(e) = vfs::chdir(path) {
+        kprintln!("cd: {}: {}", path, e);
+    }
+}
+
+/// Print the working directory.
+pub fn pwd() {
+    kprintln!("{}", vfs::cwd());
+}
+
+/// Run a vfs operation on every path argument: mkdir <dir>..., rm <path>...
+pub fn each_path(name: &str, args: &[&str], op: fn(&str) -> Result<(), vfs::Error>) {
+    if args.is_empty() {
+        kprintln!("usage: {} <path>...", name);
+    }
+    for path in args {
+        if let Err(e) = op(path) {
+            kprintln!("{}: {}: {}", name, path, e);
+        }
+    }
+}
+
+/// Rename a file: mv <old> <new>.
+pub fn mv(args: &[&str]) {
+    match args {
+        [old, new] => {
+            if let Err(e) = vfs::rename(old, new) {
+                kprintln!("mv: {}: {}", old, e);
+            }
+        }
+        _ => kprintln!("usage: mv <old> <new>"),
+    }
+}
+
+/// Create a symbolic link: ln <target> <link>.
+pub fn ln(args: &[&str]) {
+    match args {
+        [target, link] => {
+            if let Err(e) = vfs::symlink(target, link) {
+                kprintln!("ln: {}: {}", link, e);
+            }
+        }
+        _ => kprintln!("usage: ln <target> <link>"),
+    }
+}
+
+/// Print the attributes of a file: stat <path>.
+pub fn stat(args: &[&str]) {
+    let path = match args {
+        [path] => *path,
+        _ => {
+            kprintln!("usage: stat <path>");
+            return;
+        }
+    };
+    match vfs::lstat(path) {
+        Ok(metadata) => {
+            kprintln!("  File: {}", path);
+            kprintln!("  Size: {}", metadata.size);
+            kprintln!(" Inode: {}", metadata.ino);
+            kprintln!(" Links: {}", metadata.links);
+            kprintln!(
+                "Access: {:04o}/{}{}",
+                metadata.mode,
+                metadata.kind.as_char(),
+                core::str::from_utf8(&mode_string(metadata.mode)).unwrap()
+            );
+        }
+        Err(e) => kprintln!("stat: {}: {}", path, e),
+    }
+}
+
+/// List the mounted filesystems, or mount one:
+/// mount [<type> <source> <dir> [size]].
+pub fn mount(args: &[&str]) {
+    let (fstype, source, dir, size) = match args {
+        [] => {
+            for m in vfs::mounts() {
+                kprintln!("{} on {} type {}", m.source, m.path, m.fstype);
+            }
+            return;
+        }
+        [fstype, source, dir] => (*fstype, *source, *dir, None),
+        [fstype, source, dir, size] => match tmpfs::parse_size(size) {
+            Some(size) => (*fstype, *source, *dir, Some(size)),
+            None => {
+                kprintln!("mount: {}: invalid size", size);
+                return;
+            }
+        },
+        _ => {
+            kprintln!("usage: mount [<type> <source> <dir> [size]]");
+            return;
+        }
+    };
+    let fs: Arc<dyn vfs::FileSystem> = match fstype {
+        "tmpfs" => Arc::new(TmpFs::new(size)),
+        "ext2" | "vfat" | "iso9660" => {
+            let device = match partition::find_device(source) {
+                Some(device) => device,
+                None => {
+                    kprintln!("mount: {}: no such device", source);
+                    return;
+                }
+            };
+            let fs: Result<Arc<dyn vfs::FileSystem>, vfs::Error> = match fstype {
+                "ext2" => Ext2Fs::new(device).map(|fs| Arc::new(fs) as _),
+                "vfat" => FatFs::new(device).map(|fs| Arc::new(fs) as _),
+                _ => IsoFs::new(device).map(|fs| Arc::new(fs) as _),
+            };
+            match fs {
+                Ok(fs) => fs,
+                Err(e) => {
+                    kprintln!("mount: {}: {}", source, e);
+                    return;
+                }
+            }
+        }
+        _ => {
+            kprintln!("mount: {}: unknown filesystem type", fstype);
+            return;
+        }
+    };
+    if let Err(e) = vfs::mount(source, dir, fs) {
+        kprintln!("mount: {}: {}", dir, e);
+    }
+}
+
+/// Detach a filesystem: umount <dir>.
+pub fn umount(args: &[&str]) {
+    match args {
+        [dir] => {
+            if let Err(e) = vfs::umount(dir) {
+                kprintln!("umount: {}: {}", dir, e);
+            }
+        }
+        _ => kprintln!("usage: umount <dir>"),
+    }
+}
+
+/// Replace the content of a file with a line: write <file> [word]...
+pub fn write(args: &[&str]) {
+    let path = match args.first() {
+        Some(path) => *path,
+        None => {
+            kprintln!("usage: write <file> [word]...");
+            return;
+        }
+    };
+    let flags = vfs::OpenFlags::WRITE | vfs::OpenFlags::CREATE | vfs::OpenFlags::TRUNCATE;
+    let fd = match vfs::open(path, flags) {
+        Ok(fd) => fd,
+        Err(e) => {
+            kprintln!("write: {}: {}", path, e);
+            return;
+        }
+    };
+    let mut line = args[1..].join(" ");
+    line.push('\n');
+    if let Err(e) = vfs::write(fd, line.as_bytes()) {
+        kprintln!("write: {}: {}", path, e);
+    }
+    let _ = vfs::close(fd);
+}
+
+/// Set the size of a file: truncate <file> <size>.
+pub fn truncate(args: &[&str]) {
+    match args {
+        [path, size] => match tmpfs::parse_size(size) {
+            Some(size) => {
+                if let Err(e) = vfs::truncate(path, size as u64) {
+                    kprintln!("truncate: {}: {}", path, e);
+                }
+            }
+            None => kprintln!("truncate: {}: invalid size", size),
+        },
+        _ => kprintln!("usage: truncate <file> <size>"),
+    }
+}
@@ -4,8 +4,11 @@ use crate::{
     keyboard::{DecodedKey, KeyCode, KeyEvent, KeyState, KEYBOARD},
     kprint, kprintln,
     port::PortReadOnly,
-    screen_setcolor, screen_setfgcolor,
-    vga_buffer::color::{Color, ColorCode},
+    screen_setcolor, screen_setfgcolor, vfs,
+    vga_buffer::{
+        color::{Color, ColorCode},
+        BUFFER_HEIGHT,
+    },
 };
 
 mod command;
@@ -34,8 +37,7 @@ impl Command {
     }
 
     fn get_key(&mut self, scancode: u8) -> Option<DecodedKey> {
-        // TODO Remove interupt during lock to avoid dead lock.
-        let mut keyboard_lock = unsafe { KEYBOARD.lock() };
+        let mut keyboard_lock = KEYBOARD.lock_irq();
         if let Ok(Some(key_event)) = keyboard_lock.add_byte(scancode) {
             match key_event {
                 KeyEvent {
@@ -61,12 +63,7 @@ impl Command {
 
     fn read(&mut self) -> bool {
         loop {
-            let mut keyboard_cmd = PortReadOnly::<u8>::new(0x64);
-            let mut keyboard_data = PortReadOnly::<u8>::new(0x60);
-            while unsafe { keyboard_cmd.read() } & 1 != 1 {
-                core::hint::spin_loop();
-            }
-            let scancode = unsafe { keyboard_data.read() };
+            let scancode = read_scancode();
             if let Some(k) = self.get_key(scancode) {
                 match k {
                     DecodedKey::Unicode(c) => match c {
@@ -119,6 +116,59 @@ impl Command {
     }
 }
 
+/// Block until a scancode is available and return it.
+fn read_scancode() -> u8 {
+    let mut keyboard_cmd = PortReadOnly::<u8>::new(0x64);
+    let mut keyboard_data = PortReadOnly::<u8>::new(0x60);
+    while unsafe { keyboard_cmd.read() } & 1 != 1 {
+        core::hint::spin_loop();
+    }
+    unsafe { keyboard_data.read() }
+}
+
+/// Block until a key is pressed and return it.
+fn wait_key() -> DecodedKey {
+    loop {
+        let scancode = read_scancode();
+        let mut keyboard_lock = KEYBOARD.lock_irq();
+        if let Ok(Some(key_event)) = keyboard_lock.add_byte(scancode) {
+            if let Some(key) = keyboard_lock.process_keyevent(key_event) {
+                return key;
+            }
+        }
+    }
+}
+
+const PAGER_PROMPT: &str = "-- more -- (q to quit)";
+
+/// Stop the output every screen until a key is pressed.
+struct Pager {
+    lines: usize,
+}
+
+impl Pager {
+    fn new() -> Self {
+        Self { lines: 0 }
+    }
+
+    /// Account for a printed line.
+    ///
+    /// Return false if the user asked to stop the output.
+    fn next_line(&mut self) -> bool {
+        self.lines += 1;
+        if self.lines < BUFFER_HEIGHT - 1 {
+            return true;
+        }
+        self.lines = 0;
+        kprint!("{}", PAGER_PROMPT);
+        let key = wait_key();
+        for _ in 0..PAGER_PROMPT.len() {
+            kprint!("\x08");
+        }
+        key != DecodedKey::Unicode('q')
+    }
+}
+
 fn print_welcome() {
     screen_setcolor!(ColorCode::default());
     screen_setfgcolor!(Color::White);
@@ -157,6 +207,33 @@ pub fn kshell() {
             "info" => command::info(),
             "read_serial" => command::read_serial(),
             "echo" => command::echo(&args[1..nb_arg]),
+            "hexdump" => command::hexdump(&args[1..nb_arg]),
+            "stack" => command::stack(),
+            "peek8" => command::peek::<u8>(args[0], &args[1..nb_arg]),
+            "peek16" => command::peek::<u16>(args[0], &args[1..nb_arg]),
+            "peek32" => command::peek::<u32>(args[0], &args[1..nb_arg]),
+            "poke8" => command::poke::<u8>(args[0], &args[1..nb_arg]),
+            "poke16" => command::poke::<u16>(args[0], &args[1..nb_arg]),
+            "poke32" => command::poke::<u32>(args[0], &args[1..nb_arg]),
+            "vtop" => command::vtop(&args[1..nb_arg]),
+            "meminfo" => command::meminfo(),
+            "leaks" => command::leaks(&args[1..nb_arg]),
+            "disks" => command::disks(),
+            "readsector" => command::readsector(&args[1..nb_arg]),
+            "partitions" => command::partitions(),
+            "ls" => command::ls(&args[1..nb_arg]),
+            "cat" => command::cat(&args[1..nb_arg]),
+            "cd" => command::cd(&args[1..nb_arg]),
+            "pwd" => command::pwd(),
+            "mkdir" => command::each_path(args[0], &args[1..nb_arg], vfs::mkdir),
+            "rm" => command::each_path(args[0], &args[1..nb_arg], vfs::unlink),
+            "mv" => command::mv(&args[1..nb_arg]),
+            "ln" => command::ln(&args[1..nb_arg]),
+            "stat" => command::stat(&args[1..nb_arg]),
+            "write" => command::write(&args[1..nb_arg]),
+            "truncate" => command::truncate(&args[1..nb_arg]),
+            "mount" => command::mount(&args[1..nb_arg]),
+            "umount" => command::umount(&args[1..nb_arg]),
             _ => {}
         }
     }
@@ -3,17 +3,30 @@
 #![no_std]
 #![no_main]
 
+extern crate alloc;
+
 use core::panic::PanicInfo;
 
+pub mod ata;
+pub mod backtrace;
+pub mod block;
+pub mod ext2;
+pub mod fat;
+pub mod initrd;
+pub mod iso9660;
 pub mod keyboard;
 pub mod kshell;
+pub mod memory;
+pub mod multiboot;
+pub mod once;
+pub mod partition;
 pub mod port;
 pub mod serial;
 pub mod spinlock;
+pub mod tmpfs;
+pub mod vfs;
 pub mod vga_buffer;
 
-use keyboard::KEYBOARD;
-
 const VERSION: &str = "1.0.0";
 
 #[allow(dead_code)]
@@ -24,24 +37,33 @@ extern "C" {
 
 /// Initialisation of the kernel.
 ///
+/// - init the vga writer
 /// - clear the screen
 /// - set the color to default
-/// - init the serial module
-fn kinit() {
+/// - init the serial module on the detected COM port
+/// - keep the Multiboot2 boot information
+/// - init the kernel heap
+/// - detect the ATA disks and their partitions
+/// - mount the initrd as the root filesystem and a tmpfs on /tmp
+fn kinit(magic: u32, boot_info: usize) {
+    vga_buffer::init(vga_buffer::VGA_BUFFER);
     screen_clear!();
     screen_setcolor!(Default::default());
-    unsafe {
-        KEYBOARD.is_locked();
-    } // Needed don't known why but whitout spinnlock is lock.
-      // TODO Remove interupt during lock to avoid dead lock.
-    unsafe { serial::SERIAL.lock().init() };
+    serial::init();
+    multiboot::init(magic, boot_info);
+    memory::heap::init();
+    ata::init();
+    partition::init();
+    initrd::init();
+    tmpfs::init();
     kprintln!("42");
 }
 
-/// Entry point of the rust part.
+/// Entry point of the rust part, called with the Multiboot2 magic value
+/// and the address of the boot information.
 #[no_mangle]
-pub extern "C" fn kmain() {
-    kinit();
+pub extern "C" fn kmain(magic: u32, boot_info: usize) {
+    kinit(magic, boot_info);
     loop {
         kshell::kshell();
     }
@@ -50,6 +72,11 @@ pub extern "C" fn kmain() {
 /// Panic handler.
 #[panic_handler]
 fn panic(info: &PanicInfo) -> ! {
-    kprintln!("{:?}", info);
+    if vga_buffer::writer::WRITER.is_completed() {
+        kprintln!("{:?}", info);
+    }
+    if serial::SERIAL.is_completed() {
+        kdebugln!("{:?}", info);
+    }
     loop {}
 }
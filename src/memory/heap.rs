@@ -0,0 +1,243 @@
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    mem::{align_of, size_of},
+    ptr::{addr_of_mut, null_mut},
+};
+
+use crate::spinlock::Spinlock;
+
+#[cfg(feature = "alloc_trace")]
+use super::trace;
+
+pub const HEAP_SIZE: usize = 0x10_0000;
+
+static mut HEAP_AREA: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+
+pub static HEAP: Spinlock<Heap> = Spinlock::with_name(Heap::new(), "HEAP");
+
+#[global_allocator]
+static ALLOCATOR: KernelAllocator = KernelAllocator;
+
+/// Header written at the start of every free block.
+struct FreeBlock {
+    size: usize,
+    next: *mut FreeBlock,
+}
+
+/// Snapshot of the heap usage.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeapStats {
+    pub total: usize,
+    pub used: usize,
+    pub free: usize,
+    pub free_blocks: usize,
+    pub largest_free: usize,
+    pub allocations: usize,
+    pub deallocations: usize,
+}
+
+impl HeapStats {
+    /// Part of the free memory which is not in the largest free block,
+    /// in percent.
+    pub fn fragmentation(&self) -> usize {
+        if self.free == 0 {
+            return 0;
+        }
+        100 - self.largest_free * 100 / self.free
+    }
+}
+
+/// First-fit allocator keeping an address ordered list of free blocks.
+pub struct Heap {
+    head: *mut FreeBlock,
+    total: usize,
+    used: usize,
+    allocations: usize,
+    deallocations: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Heap {
+    pub const fn new() -> Self {
+        Self {
+            head: null_mut(),
+            total: 0,
+            used: 0,
+            allocations: 0,
+            deallocations: 0,
+        }
+    }
+
+    /// Give the memory [start, start + size) to the heap.
+    ///
+    /// # Safety
+    ///
+    /// The memory must be unused and valid for the kernel lifetime.
+    pub unsafe fn init(&mut self, start: usize, size: usize) {
+        let aligned = align_up(start, align_of::<FreeBlock>());
+        let size = (size - (aligned - start)) & !(align_of::<FreeBlock>() - 1);
+        self.total = size;
+        self.insert(aligned, size);
+    }
+
+    /// Return the size and alignment really used for a layout.
+    fn block_layout(layout: Layout) -> (usize, usize) {
+        let align = layout.align().max(align_of::<FreeBlock>());
+        let size = align_up(
+            layout.size().max(size_of::<FreeBlock>()),
+            align_of::<FreeBlock>(),
+        );
+        (size, align)
+    }
+
+    /// Allocate a block for the layout, return null if none is large enough.
+    pub fn allocate(&mut self, layout: Layout) -> *mut u8 {
+        let (size, align) = Self::block_layout(layout);
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut block = self.head;
+        unsafe {
+            while !block.is_null() {
+                let start = block as usize;
+                let end = start + (*block).size;
+                if let Some(addr) = fit(start, end, size, align) {
+                    let next = (*block).next;
+                    if prev.is_null() {
+                        self.head = next;
+                    } else {
+                        (*prev).next = next;
+                    }
+                    if addr != start {
+                        self.insert(start, addr - start);
+                    }
+                    if addr + size != end {
+                        self.insert(addr + size, end - addr - size);
+                    }
+                    self.used += size;
+                    self.allocations += 1;
+                    return addr as *mut u8;
+                }
+                prev = block;
+                block = (*block).next;
+            }
+        }
+        null_mut()
+    }
+
+    /// Give back a block allocated with the same layout.
+    ///
+    /// # Safety
+    ///
+    /// ptr must come from `allocate` of this heap with this layout, and
+    /// not be given back twice.
+    pub unsafe fn deallocate(&mut self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = Self::block_layout(layout);
+        self.used -= size;
+        self.deallocations += 1;
+        self.insert(ptr as usize, size);
+    }
+
+    /// Insert a free block keeping the list ordered and merge it with its
+    /// neighbours.
+    unsafe fn insert(&mut self, addr: usize, size: usize) {
+        let mut prev: *mut FreeBlock = null_mut();
+        let mut next = self.head;
+        while !next.is_null() && (next as usize) < addr {
+            prev = next;
+            next = (*next).next;
+        }
+
+        let block = addr as *mut FreeBlock;
+        block.write(FreeBlock { size, next });
+        if !next.is_null() && addr + size == next as usize {
+            (*block).size += (*next).size;
+            (*block).next = (*next).next;
+        }
+
+        if prev.is_null() {
+            self.head = block;
+        } else if prev as usize + (*prev).size == addr {
+            (*prev).size += (*block).size;
+            (*prev).next = (*block).next;
+        } else {
+            (*prev).next = block;
+        }
+    }
+
+    /// Return the current usage of the heap.
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            total: self.total,
+            used: self.used,
+            allocations: self.allocations,
+            deallocations: self.deallocations,
+            ..Default::default()
+        };
+        let mut block = self.head;
+        while !block.is_null() {
+            unsafe {
+                stats.free += (*block).size;
+                stats.free_blocks += 1;
+                stats.largest_free = stats.largest_free.max((*block).size);
+                block = (*block).next;
+            }
+        }
+        stats
+    }
+}
+
+/// Return the first address in [start, end) where size bytes aligned on
+/// align fit, leaving either no gap or a gap large enough to stay a free
+/// block before and after it.
+fn fit(start: usize, end: usize, size: usize, align: usize) -> Option<usize> {
+    let mut addr = align_up(start, align);
+    while addr != start && addr - start < size_of::<FreeBlock>() {
+        addr = align_up(addr + 1, align);
+    }
+    let alloc_end = addr.checked_add(size)?;
+    if alloc_end > end {
+        return None;
+    }
+    let rest = end - alloc_end;
+    if rest != 0 && rest < size_of::<FreeBlock>() {
+        return None;
+    }
+    Some(addr)
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// Kernel global allocator backed by `HEAP`.
+pub struct KernelAllocator;
+
+unsafe impl GlobalAlloc for KernelAllocator {
+    #[inline(never)]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = HEAP.lock_irq().allocate(layout);
+        #[cfg(feature = "alloc_trace")]
+        if !ptr.is_null() {
+            trace::record(ptr as usize, layout.size(), trace::caller());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        #[cfg(feature = "alloc_trace")]
+        trace::forget(ptr as usize);
+        HEAP.lock_irq().deallocate(ptr, layout);
+    }
+}
+
+/// Give the static heap area to the kernel allocator.
+pub fn init() {
+    unsafe {
+        HEAP.lock_irq()
+            .init(addr_of_mut!(HEAP_AREA) as usize, HEAP_SIZE);
+    }
+}
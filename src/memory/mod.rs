@@ -0,0 +1,90 @@
+use core::arch::asm;
+
+pub mod heap;
+#[cfg(feature = "alloc_trace")]
+pub mod trace;
+
+pub const PAGE_SIZE: usize = 0x1000;
+
+const CR0_PG: usize = 1 << 31;
+const CR4_PSE: usize = 1 << 4;
+
+const PDE_PRESENT: usize = 1 << 0;
+const PDE_PAGE_SIZE: usize = 1 << 7;
+
+/// Read the control register 0.
+fn read_cr0() -> usize {
+    let value: usize;
+    unsafe {
+        asm!("mov {}, cr0", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Read the control register 3 (page directory base).
+fn read_cr3() -> usize {
+    let value: usize;
+    unsafe {
+        asm!("mov {}, cr3", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Read the control register 4.
+fn read_cr4() -> usize {
+    let value: usize;
+    unsafe {
+        asm!("mov {}, cr4", out(reg) value, options(nomem, nostack, preserves_flags));
+    }
+    value
+}
+
+/// Return true if paging is enabled.
+pub fn paging_enabled() -> bool {
+    read_cr0() & CR0_PG != 0
+}
+
+/// Translate a virtual address to a physical address.
+///
+/// When paging is disabled every address is identity mapped.
+/// Return None if the address is not mapped.
+pub fn translate(vaddr: usize) -> Option<usize> {
+    if !paging_enabled() {
+        return Some(vaddr);
+    }
+    let directory = (read_cr3() & !(PAGE_SIZE - 1)) as *const usize;
+    let pde = unsafe { directory.add(vaddr >> 22).read_volatile() };
+    if pde & PDE_PRESENT == 0 {
+        return None;
+    }
+    if pde & PDE_PAGE_SIZE != 0 && read_cr4() & CR4_PSE != 0 {
+        return Some((pde & 0xffc0_0000) | (vaddr & 0x003f_ffff));
+    }
+    let table = (pde & !(PAGE_SIZE - 1)) as *const usize;
+    let pte = unsafe { table.add((vaddr >> 12) & 0x3ff).read_volatile() };
+    if pte & PDE_PRESENT == 0 {
+        return None;
+    }
+    Some((pte & !(PAGE_SIZE - 1)) | (vaddr & (PAGE_SIZE - 1)))
+}
+
+/// Return true if every byte of [addr, addr + len) is mapped.
+pub fn is_mapped(addr: usize, len: usize) -> bool {
+    if len == 0 {
+        return true;
+    }
+    let end = match addr.checked_add(len - 1) {
+        Some(end) => end,
+        None => return false,
+    };
+    let mut page = addr & !(PAGE_SIZE - 1);
+    loop {
+        if translate(page).is_none() {
+            return false;
+        }
+        match page.checked_add(PAGE_SIZE) {
+            Some(next) if next <= end => page = next,
+            _ => return true,
+        }
+    }
+}
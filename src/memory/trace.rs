@@ -0,0 +1,89 @@
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::spinlock::Spinlock;
+
+const TRACE_SIZE: usize = 256;
+
+/// Number of frames between `caller` and the code asking for memory
+/// (`KernelAllocator::alloc` and the `__rust_alloc` shim).
+const CALLER_SKIP: usize = 2;
+
+/// Runtime switch of the allocation tracing.
+pub static ENABLED: AtomicBool = AtomicBool::new(true);
+
+static TRACER: Spinlock<Tracer> = Spinlock::with_name(Tracer::new(), "TRACER");
+
+/// A live allocation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Allocation {
+    pub ptr: usize,
+    pub size: usize,
+    pub caller: usize,
+}
+
+/// Fixed size tables of live allocations, the heap can't be used to track
+/// itself.
+struct Tracer {
+    live: [Option<Allocation>; TRACE_SIZE],
+    snapshot: [Option<Allocation>; TRACE_SIZE],
+    dropped: usize,
+}
+
+impl Tracer {
+    const fn new() -> Self {
+        Self {
+            live: [None; TRACE_SIZE],
+            snapshot: [None; TRACE_SIZE],
+            dropped: 0,
+        }
+    }
+}
+
+/// Return the address the allocation was requested from.
+#[inline(always)]
+pub fn caller() -> usize {
+    crate::backtrace::return_address(CALLER_SKIP)
+}
+
+/// Record a new allocation.
+pub fn record(ptr: usize, size: usize, caller: usize) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut tracer = TRACER.lock_irq();
+    match tracer.live.iter_mut().find(|a| a.is_none()) {
+        Some(slot) => *slot = Some(Allocation { ptr, size, caller }),
+        None => tracer.dropped += 1,
+    }
+}
+
+/// Forget a freed allocation.
+pub fn forget(ptr: usize) {
+    let mut tracer = TRACER.lock_irq();
+    if let Some(slot) = tracer
+        .live
+        .iter_mut()
+        .find(|a| a.map_or(false, |a| a.ptr == ptr))
+    {
+        *slot = None;
+    }
+}
+
+/// Save the live allocations to compare them later.
+pub fn snapshot() {
+    let mut tracer = TRACER.lock_irq();
+    tracer.snapshot = tracer.live;
+}
+
+/// Call f on every live allocation missing from the last snapshot.
+///
+/// Return the number of allocations which could not be recorded.
+pub fn diff<F: FnMut(&Allocation)>(mut f: F) -> usize {
+    let tracer = TRACER.lock_irq();
+    for a in tracer.live.iter().flatten() {
+        if !tracer.snapshot.contains(&Some(*a)) {
+            f(a);
+        }
+    }
+    tracer.dropped
+}
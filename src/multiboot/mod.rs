@@ -0,0 +1,133 @@
+use core::{slice, str};
+
+use crate::once::Once;
+
+/// Value of eax when the kernel is started by a Multiboot2 boot loader.
+pub const BOOTLOADER_MAGIC: u32 = 0x36d7_6289;
+
+const TAG_END: u32 = 0;
+const TAG_CMDLINE: u32 = 1;
+const TAG_BOOTLOADER_NAME: u32 = 2;
+const TAG_MODULE: u32 = 3;
+
+/// Address of the boot information given by the boot loader.
+static BOOT_INFO: Once<BootInfo> = Once::with_name("BOOT_INFO");
+
+/// Boot information structure of Multiboot2.
+#[derive(Debug, Copy, Clone)]
+pub struct BootInfo {
+    addr: usize,
+}
+
+/// A tag of the boot information.
+#[derive(Debug, Copy, Clone)]
+struct Tag {
+    kind: u32,
+    /// Content of the tag, after the type and size fields.
+    data: &'static [u8],
+}
+
+/// A file loaded in memory by the boot loader with a `module2` line.
+#[derive(Debug, Copy, Clone)]
+pub struct Module {
+    pub start: usize,
+    pub end: usize,
+    /// Arguments of the `module2` line.
+    pub cmdline: &'static str,
+}
+
+impl Module {
+    /// Content of the module.
+    pub fn data(&self) -> &'static [u8] {
+        unsafe { slice::from_raw_parts(self.start as *const u8, self.end - self.start) }
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+/// Read a null terminated string.
+fn read_str(data: &'static [u8]) -> &'static str {
+    let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    str::from_utf8(&data[..len]).unwrap_or("")
+}
+
+impl BootInfo {
+    fn data(&self) -> &'static [u8] {
+        let total_size = unsafe { *(self.addr as *const u32) } as usize;
+        unsafe { slice::from_raw_parts(self.addr as *const u8, total_size) }
+    }
+
+    /// Iterate over the tags, which are 8 bytes aligned after the 8 bytes
+    /// header.
+    fn tags(&self) -> impl Iterator<Item = Tag> {
+        let data = self.data();
+        let mut offset = 8;
+        core::iter::from_fn(move || {
+            if offset + 8 > data.len() {
+                return None;
+            }
+            let kind = read_u32(data, offset);
+            let size = read_u32(data, offset + 4) as usize;
+            if kind == TAG_END || size < 8 || offset + size > data.len() {
+                return None;
+            }
+            let tag = Tag {
+                kind,
+                data: &data[offset + 8..offset + size],
+            };
+            offset += (size + 7) & !7;
+            Some(tag)
+        })
+    }
+
+    fn find_str(&self, kind: u32) -> Option<&'static str> {
+        self.tags()
+            .find(|t| t.kind == kind)
+            .map(|t| read_str(t.data))
+    }
+
+    /// Command line of the kernel.
+    pub fn cmdline(&self) -> Option<&'static str> {
+        self.find_str(TAG_CMDLINE)
+    }
+
+    pub fn bootloader_name(&self) -> Option<&'static str> {
+        self.find_str(TAG_BOOTLOADER_NAME)
+    }
+
+    /// Iterate over the modules loaded by the boot loader.
+    pub fn modules(&self) -> impl Iterator<Item = Module> {
+        self.tags()
+            .filter(|t| t.kind == TAG_MODULE && t.data.len() >= 8)
+            .map(|t| Module {
+                start: read_u32(t.data, 0) as usize,
+                end: read_u32(t.data, 4) as usize,
+                cmdline: read_str(&t.data[8..]),
+            })
+            .filter(|m| m.start <= m.end)
+    }
+}
+
+/// Keep the boot information if the kernel was started by a Multiboot2
+/// boot loader.
+pub fn init(magic: u32, addr: usize) {
+    if magic == BOOTLOADER_MAGIC && addr != 0 {
+        BOOT_INFO.call_once(|| BootInfo { addr });
+    }
+}
+
+/// Return the boot information, None if the boot loader isn't Multiboot2.
+pub fn boot_info() -> Option<&'static BootInfo> {
+    BOOT_INFO.get()
+}
+
+/// Return the first module whose command line begins with the word name.
+pub fn find_module(name: &str) -> Option<Module> {
+    boot_info()?
+        .modules()
+        .find(|m| m.cmdline.split(' ').next() == Some(name))
+}
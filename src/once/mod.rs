@@ -0,0 +1,125 @@
+use core::{
+    cell::{Cell, UnsafeCell},
+    mem::MaybeUninit,
+    ops::Deref,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+const INCOMPLETE: u8 = 0;
+const RUNNING: u8 = 1;
+const COMPLETE: u8 = 2;
+
+/// A value initialised once at runtime.
+///
+/// Dereferencing it before initialisation panics with its name.
+pub struct Once<T> {
+    state: AtomicU8,
+    name: &'static str,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Once<T> {
+    /// Create an uninitialised Once.
+    pub const fn new() -> Self {
+        Self::with_name("unnamed")
+    }
+
+    /// Create an uninitialised Once with a name used in the panic message.
+    pub const fn with_name(name: &'static str) -> Self {
+        Self {
+            state: AtomicU8::new(INCOMPLETE),
+            name,
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initialise the value with f if it's not done yet and return it.
+    ///
+    /// If another caller is running its initialisation wait for it.
+    pub fn call_once<F: FnOnce() -> T>(&self, f: F) -> &T {
+        match self
+            .state
+            .compare_exchange(INCOMPLETE, RUNNING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                unsafe { (*self.data.get()).as_mut_ptr().write(f()) };
+                self.state.store(COMPLETE, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) == RUNNING {
+                    core::hint::spin_loop();
+                }
+            }
+        }
+        unsafe { &*(*self.data.get()).as_ptr() }
+    }
+
+    /// Return the value if it's initialised.
+    pub fn get(&self) -> Option<&T> {
+        if self.is_completed() {
+            Some(unsafe { &*(*self.data.get()).as_ptr() })
+        } else {
+            None
+        }
+    }
+
+    /// Return true if the value is initialised.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == COMPLETE
+    }
+}
+
+impl<T> Deref for Once<T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        match self.get() {
+            Some(value) => value,
+            None => panic!("{} used before initialisation", self.name),
+        }
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if self.is_completed() {
+            unsafe { (*self.data.get()).as_mut_ptr().drop_in_place() };
+        }
+    }
+}
+
+/// A value initialised by a function on first access.
+pub struct Lazy<T, F = fn() -> T> {
+    once: Once<T>,
+    init: Cell<Option<F>>,
+}
+
+unsafe impl<T: Send + Sync, F: Send> Sync for Lazy<T, F> {}
+
+impl<T, F> Lazy<T, F> {
+    /// Create a Lazy which will be initialised by f.
+    pub const fn new(f: F) -> Self {
+        Self {
+            once: Once::new(),
+            init: Cell::new(Some(f)),
+        }
+    }
+}
+
+impl<T, F: FnOnce() -> T> Deref for Lazy<T, F> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        self.once.call_once(|| match self.init.take() {
+            Some(f) => f(),
+            None => panic!("Lazy initialisation function already used"),
+        })
+    }
+}
@@ -0,0 +1,427 @@
+use alloc::{format, string::String, sync::Arc, vec, vec::Vec};
+use core::fmt;
+
+use crate::{
+    ata,
+    block::{self, BlockDevice},
+    once::Once,
+};
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_ENTRIES: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+
+const MBR_EMPTY: u8 = 0x00;
+const MBR_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+
+/// Number of the first logical partition, as in Linux.
+const FIRST_LOGICAL: usize = 5;
+/// Bound on the EBR chain, against loops in a corrupted table.
+const MAX_LOGICAL: usize = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_HEADER_MIN_SIZE: usize = 92;
+const GPT_ENTRY_MIN_SIZE: usize = 128;
+const GPT_ENTRY_MAX_SIZE: usize = 512;
+const GPT_MAX_ENTRIES: usize = 1024;
+
+static PARTITIONS: Once<Vec<Arc<Partition>>> = Once::with_name("PARTITIONS");
+
+/// Indicates differente error condition of a partition table.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Error {
+    Device(block::Error),
+    /// No MBR signature on the first sector.
+    NoTable,
+    /// Bad signature, size or location of the GPT header.
+    BadGptHeader,
+    /// Checksum of the GPT header doesn't match.
+    BadGptHeaderCrc,
+    /// Checksum of the GPT partition entries doesn't match.
+    BadGptEntriesCrc,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Device(e) => write!(f, "{}", e),
+            Error::NoTable => write!(f, "no partition table"),
+            Error::BadGptHeader => write!(f, "bad GPT header"),
+            Error::BadGptHeaderCrc => write!(f, "bad GPT header CRC32"),
+            Error::BadGptEntriesCrc => write!(f, "bad GPT partition entries CRC32"),
+        }
+    }
+}
+
+impl From<block::Error> for Error {
+    fn from(e: block::Error) -> Self {
+        Error::Device(e)
+    }
+}
+
+/// Type of a partition, from the MBR type byte or the GPT type GUID.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum PartitionType {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+impl fmt::Display for PartitionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            PartitionType::Mbr(0x01) => "FAT12",
+            PartitionType::Mbr(0x04) | PartitionType::Mbr(0x06) | PartitionType::Mbr(0x0e) => {
+                "FAT16"
+            }
+            PartitionType::Mbr(0x07) => "NTFS/exFAT",
+            PartitionType::Mbr(0x0b) | PartitionType::Mbr(0x0c) => "FAT32",
+            PartitionType::Mbr(0x82) => "Linux swap",
+            PartitionType::Mbr(0x83) => "Linux",
+            PartitionType::Mbr(0xef) => "EFI System",
+            PartitionType::Mbr(t) => return f.pad(&format!("MBR {:#04x}", t)),
+            PartitionType::Gpt(guid) => match guid.fields() {
+                (0xc12a7328, 0xf81f, 0x11d2, 0xba4b, 0x00a0c93ec93b) => "EFI System",
+                (0x21686148, 0x6449, 0x6e6f, 0x744e, 0x656564454649) => "BIOS boot",
+                (0x0fc63daf, 0x8483, 0x4772, 0x8e79, 0x3d69d8477de4) => "Linux",
+                (0x0657fd6d, 0xa4ab, 0x43c4, 0x84e5, 0x0933c84b4f4f) => "Linux swap",
+                (0xebd0a0a2, 0xb9e5, 0x4433, 0x87c0, 0x68b6b72699c7) => "Basic data",
+                _ => return f.pad(&format!("{}", guid)),
+            },
+        };
+        f.pad(name)
+    }
+}
+
+/// A GUID as stored on disk (mixed endian).
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Guid([u8; 16]);
+
+impl Guid {
+    /// Return the five groups of the textual form.
+    fn fields(&self) -> (u32, u16, u16, u16, u64) {
+        let b = &self.0;
+        (
+            u32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+            u16::from_le_bytes([b[4], b[5]]),
+            u16::from_le_bytes([b[6], b[7]]),
+            u16::from_be_bytes([b[8], b[9]]),
+            u64::from_be_bytes([0, 0, b[10], b[11], b[12], b[13], b[14], b[15]]),
+        )
+    }
+
+    fn is_zero(&self) -> bool {
+        self.0.iter().all(|b| *b == 0)
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (a, b, c, d, e) = self.fields();
+        write!(f, "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}", a, b, c, d, e)
+    }
+}
+
+/// A slice of a block device seen as its own block device.
+pub struct Partition {
+    device: Arc<dyn BlockDevice>,
+    name: String,
+    label: String,
+    kind: PartitionType,
+    start: u64,
+    sectors: u64,
+}
+
+impl Partition {
+    /// Device name of the partition (hda1, hdb5...).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Name stored in the GPT entry, empty for MBR partitions.
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn kind(&self) -> PartitionType {
+        self.kind
+    }
+
+    /// First sector of the partition on its device.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+}
+
+impl BlockDevice for Partition {
+    fn sector_size(&self) -> usize {
+        self.device.sector_size()
+    }
+
+    fn sector_count(&self) -> u64 {
+        self.sectors
+    }
+
+    fn read_sectors(&self, lba: u64, buf: &mut [u8]) -> Result<(), block::Error> {
+        self.check_range(lba, buf.len())?;
+        self.device.read_sectors(self.start + lba, buf)
+    }
+
+    fn write_sectors(&self, lba: u64, buf: &[u8]) -> Result<(), block::Error> {
+        self.check_range(lba, buf.len())?;
+        self.device.write_sectors(self.start + lba, buf)
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        data[offset],
+        data[offset + 1],
+        data[offset + 2],
+        data[offset + 3],
+    ])
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    read_u32(data, offset) as u64 | (read_u32(data, offset + 4) as u64) << 32
+}
+
+/// CRC32 (IEEE 802.3) as used by GPT.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// A primary entry or an EBR entry of an MBR.
+struct MbrEntry {
+    kind: u8,
+    start: u64,
+    sectors: u64,
+}
+
+fn mbr_entry(sector: &[u8], i: usize) -> MbrEntry {
+    let entry = &sector[MBR_ENTRIES + i * MBR_ENTRY_SIZE..];
+    MbrEntry {
+        kind: entry[4],
+        start: read_u32(entry, 8) as u64,
+        sectors: read_u32(entry, 12) as u64,
+    }
+}
+
+/// Parse the partition table of a device.
+pub fn scan(device: &Arc<dyn BlockDevice>, name: &str) -> Result<Vec<Partition>, Error> {
+    let mut mbr = vec![0; device.sector_size()];
+    device.read_sectors(0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE {
+        return Err(Error::NoTable);
+    }
+    if (0..4).any(|i| mbr_entry(&mbr, i).kind == MBR_GPT_PROTECTIVE) {
+        return scan_gpt(device, name);
+    }
+    scan_mbr(device, name, &mbr)
+}
+
+fn new_partition(
+    device: &Arc<dyn BlockDevice>,
+    name: String,
+    label: String,
+    kind: PartitionType,
+    start: u64,
+    sectors: u64,
+) -> Option<Partition> {
+    match start.checked_add(sectors) {
+        Some(end) if sectors != 0 && end <= device.sector_count() => Some(Partition {
+            device: device.clone(),
+            name,
+            label,
+            kind,
+            start,
+            sectors,
+        }),
+        _ => None,
+    }
+}
+
+/// Parse the primary partitions and the logical partitions in the
+/// extended one.
+fn scan_mbr(
+    device: &Arc<dyn BlockDevice>,
+    name: &str,
+    mbr: &[u8],
+) -> Result<Vec<Partition>, Error> {
+    let mut partitions = Vec::new();
+    for i in 0..4 {
+        let entry = mbr_entry(mbr, i);
+        if entry.kind == MBR_EMPTY {
+            continue;
+        }
+        if MBR_EXTENDED.contains(&entry.kind) {
+            scan_ebr(device, name, entry.start, &mut partitions);
+            continue;
+        }
+        partitions.extend(new_partition(
+            device,
+            format!("{}{}", name, i + 1),
+            String::new(),
+            PartitionType::Mbr(entry.kind),
+            entry.start,
+            entry.sectors,
+        ));
+    }
+    Ok(partitions)
+}
+
+/// Follow the EBR chain of an extended partition starting at base.
+///
+/// A sector of the chain which can't be read ends it, the partitions
+/// found before are kept.
+fn scan_ebr(device: &Arc<dyn BlockDevice>, name: &str, base: u64, partitions: &mut Vec<Partition>) {
+    let mut ebr = vec![0; device.sector_size()];
+    let mut lba = base;
+    for number in FIRST_LOGICAL..FIRST_LOGICAL + MAX_LOGICAL {
+        if device.read_sectors(lba, &mut ebr).is_err() || ebr[510..512] != MBR_SIGNATURE {
+            break;
+        }
+        let logical = mbr_entry(&ebr, 0);
+        if logical.kind != MBR_EMPTY {
+            partitions.extend(new_partition(
+                device,
+                format!("{}{}", name, number),
+                String::new(),
+                PartitionType::Mbr(logical.kind),
+                lba + logical.start,
+                logical.sectors,
+            ));
+        }
+        let next = mbr_entry(&ebr, 1);
+        if next.kind == MBR_EMPTY || next.start == 0 {
+            break;
+        }
+        lba = base + next.start;
+    }
+}
+
+/// Parse the GPT, falling back on the backup header at the end of the
+/// device if the primary one is damaged.
+fn scan_gpt(device: &Arc<dyn BlockDevice>, name: &str) -> Result<Vec<Partition>, Error> {
+    match read_gpt(device, name, 1) {
+        Ok(partitions) => Ok(partitions),
+        Err(e) => read_gpt(device, name, device.sector_count() - 1).map_err(|_| e),
+    }
+}
+
+/// Check the GPT header at lba and its entries, return the partitions.
+fn read_gpt(device: &Arc<dyn BlockDevice>, name: &str, lba: u64) -> Result<Vec<Partition>, Error> {
+    let sector_size = device.sector_size();
+    let mut header = vec![0; sector_size];
+    device.read_sectors(lba, &mut header)?;
+
+    let header_size = read_u32(&header, 12) as usize;
+    if &header[0..8] != GPT_SIGNATURE
+        || header_size < GPT_HEADER_MIN_SIZE
+        || header_size > sector_size
+        || read_u64(&header, 24) != lba
+    {
+        return Err(Error::BadGptHeader);
+    }
+    let header_crc = read_u32(&header, 16);
+    header[16..20].copy_from_slice(&[0; 4]);
+    if crc32(&header[..header_size]) != header_crc {
+        return Err(Error::BadGptHeaderCrc);
+    }
+
+    let entries_lba = read_u64(&header, 72);
+    let entry_count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    if entry_count > GPT_MAX_ENTRIES
+        || entry_size < GPT_ENTRY_MIN_SIZE
+        || entry_size > GPT_ENTRY_MAX_SIZE
+        || entry_size % GPT_ENTRY_MIN_SIZE != 0
+    {
+        return Err(Error::BadGptHeader);
+    }
+    let entries_len = entry_count
+        .checked_mul(entry_size)
+        .ok_or(Error::BadGptHeader)?;
+    let mut entries = vec![0; (entries_len + sector_size - 1) / sector_size * sector_size];
+    device.read_sectors(entries_lba, &mut entries)?;
+    if crc32(&entries[..entries_len]) != read_u32(&header, 88) {
+        return Err(Error::BadGptEntriesCrc);
+    }
+
+    let mut partitions = Vec::new();
+    for (i, entry) in entries[..entries_len].chunks(entry_size).enumerate() {
+        let mut guid = [0; 16];
+        guid.copy_from_slice(&entry[0..16]);
+        let kind = Guid(guid);
+        if kind.is_zero() {
+            continue;
+        }
+        let first = read_u64(entry, 32);
+        let last = read_u64(entry, 40);
+        if last < first {
+            continue;
+        }
+        let label = entry[56..128]
+            .chunks(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .take_while(|c| *c != 0);
+        let label = core::char::decode_utf16(label)
+            .map(|c| c.unwrap_or(core::char::REPLACEMENT_CHARACTER))
+            .collect();
+        partitions.extend(new_partition(
+            device,
+            format!("{}{}", name, i + 1),
+            label,
+            PartitionType::Gpt(kind),
+            first,
+            last - first + 1,
+        ));
+    }
+    Ok(partitions)
+}
+
+/// Scan the partition tables of the ATA disks.
+pub fn init() {
+    PARTITIONS.call_once(|| {
+        let mut partitions = Vec::new();
+        for drive in ata::drives() {
+            let device: Arc<dyn BlockDevice> = drive.clone();
+            if let Ok(found) = scan(&device, drive.name()) {
+                partitions.extend(found.into_iter().map(Arc::new));
+            }
+        }
+        partitions
+    });
+}
+
+/// Return the partitions of all the disks.
+pub fn partitions() -> &'static [Arc<Partition>] {
+    &PARTITIONS
+}
+
+/// Return the partition with the given name (hda1, hdb5...).
+pub fn find(name: &str) -> Option<Arc<Partition>> {
+    partitions().iter().find(|p| p.name == name).cloned()
+}
+
+/// Return the disk, CD-ROM drive or partition with the given name.
+pub fn find_device(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    match find(name) {
+        Some(partition) => Some(partition),
+        None => match ata::find(name) {
+            Some(drive) => Some(drive),
+            None => ata::find_cdrom(name).map(|cdrom| cdrom as Arc<dyn BlockDevice>),
+        },
+    }
+}
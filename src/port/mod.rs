@@ -3,10 +3,18 @@ use core::fmt;
 use core::marker::PhantomData;
 
 pub trait PortRead {
+    /// # Safety
+    ///
+    /// Reading a port can have side effects on its device, port must be
+    /// one the caller is allowed to read.
     unsafe fn read_from_port(port: u16) -> Self;
 }
 
 pub trait PortWrite {
+    /// # Safety
+    ///
+    /// Writing a port drives its device, port must be one the caller is
+    /// allowed to write with a value the device expects.
     unsafe fn write_to_port(port: u16, value: Self);
 }
 
@@ -64,6 +72,75 @@ impl PortWrite for u32 {
     }
 }
 
+pub trait PortReadString: Sized {
+    /// # Safety
+    ///
+    /// As `PortRead`, and the device must have `buf.len()` values ready
+    /// to transfer.
+    unsafe fn read_string_from_port(port: u16, buf: &mut [Self]);
+}
+
+pub trait PortWriteString: Sized {
+    /// # Safety
+    ///
+    /// As `PortWrite`, and the device must expect `buf.len()` values.
+    unsafe fn write_string_to_port(port: u16, buf: &[Self]);
+}
+
+impl PortReadString for u8 {
+    #[inline]
+    unsafe fn read_string_from_port(port: u16, buf: &mut [u8]) {
+        asm!("rep insb", in("dx") port, inout("edi") buf.as_mut_ptr() => _,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
+impl PortReadString for u16 {
+    #[inline]
+    unsafe fn read_string_from_port(port: u16, buf: &mut [u16]) {
+        asm!("rep insw", in("dx") port, inout("edi") buf.as_mut_ptr() => _,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
+impl PortReadString for u32 {
+    #[inline]
+    unsafe fn read_string_from_port(port: u16, buf: &mut [u32]) {
+        asm!("rep insd", in("dx") port, inout("edi") buf.as_mut_ptr() => _,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
+// esi is reserved by LLVM on i386, it's swapped with a free register around
+// the string instruction.
+
+impl PortWriteString for u8 {
+    #[inline]
+    unsafe fn write_string_to_port(port: u16, buf: &[u8]) {
+        asm!("xchg esi, {src}", "rep outsb", "xchg esi, {src}",
+                src = inout(reg) buf.as_ptr() => _, in("dx") port,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
+impl PortWriteString for u16 {
+    #[inline]
+    unsafe fn write_string_to_port(port: u16, buf: &[u16]) {
+        asm!("xchg esi, {src}", "rep outsw", "xchg esi, {src}",
+                src = inout(reg) buf.as_ptr() => _, in("dx") port,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
+impl PortWriteString for u32 {
+    #[inline]
+    unsafe fn write_string_to_port(port: u16, buf: &[u32]) {
+        asm!("xchg esi, {src}", "rep outsd", "xchg esi, {src}",
+                src = inout(reg) buf.as_ptr() => _, in("dx") port,
+                inout("ecx") buf.len() => _, options(nostack, preserves_flags));
+    }
+}
+
 mod sealed {
     pub trait Access {
         const DEBUG_NAME: &'static str;
@@ -145,7 +222,10 @@ impl<T, A> PortGeneric<T, A> {
 
 impl<T: PortRead, A: PortReadAccess> PortGeneric<T, A> {
     /// Reading to the port.
-    /// unsafe
+    ///
+    /// # Safety
+    ///
+    /// The port must be valid to read, see `PortRead`.
     pub unsafe fn read(&mut self) -> T {
         T::read_from_port(self.port)
     }
@@ -153,12 +233,39 @@ impl<T: PortRead, A: PortReadAccess> PortGeneric<T, A> {
 
 impl<T: PortWrite, A: PortWriteAccess> PortGeneric<T, A> {
     /// Wrinting to the port.
-    /// unsafe
+    ///
+    /// # Safety
+    ///
+    /// The port must be valid to write with value, see `PortWrite`.
     pub unsafe fn write(&mut self, value: T) {
         T::write_to_port(self.port, value)
     }
 }
 
+impl<T: PortReadString, A: PortReadAccess> PortGeneric<T, A> {
+    /// Reading `buf.len()` values from the port (`rep ins`).
+    ///
+    /// # Safety
+    ///
+    /// The port must be valid to read and buf sized to the transfer the
+    /// device is ready for.
+    pub unsafe fn read_string(&mut self, buf: &mut [T]) {
+        T::read_string_from_port(self.port, buf)
+    }
+}
+
+impl<T: PortWriteString, A: PortWriteAccess> PortGeneric<T, A> {
+    /// Writing all the values of `buf` to the port (`rep outs`).
+    ///
+    /// # Safety
+    ///
+    /// The port must be valid to write and buf sized to the transfer the
+    /// device expects.
+    pub unsafe fn write_string(&mut self, buf: &[T]) {
+        T::write_string_to_port(self.port, buf)
+    }
+}
+
 impl<T, A: sealed::Access> fmt::Debug for PortGeneric<T, A> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("PortGeneric")
@@ -1,11 +1,43 @@
 use core::fmt;
+use core::sync::atomic::{AtomicU16, Ordering};
 
 use crate::{
+    once::Once,
     port::{Port, PortWriteOnly},
-    spinlock::Spinlock,
+    spinlock::ConsoleLock,
 };
 
-pub static mut SERIAL: Spinlock<Serial> = Spinlock::new(Serial::new(0x3f8));
+pub const COM1: u16 = 0x3f8;
+
+/// Standard COM ports, in probing order.
+const COM_PORTS: [u16; 4] = [COM1, 0x2f8, 0x3e8, 0x2e8];
+
+pub static SERIAL: Once<ConsoleLock<Serial>> = Once::with_name("SERIAL");
+
+static PORT: AtomicU16 = AtomicU16::new(COM1);
+
+/// Initialise SERIAL on the first COM port passing the loopback test.
+///
+/// Fall back on COM1 if none is found, the output is then lost.
+/// Return the port used.
+pub fn init() -> u16 {
+    let serial = SERIAL.call_once(|| {
+        let serial = COM_PORTS
+            .iter()
+            .map(|port| Serial::new(*port))
+            .find_map(|mut serial| if serial.init() { Some(serial) } else { None })
+            .unwrap_or(Serial::new(COM1));
+        ConsoleLock::with_name(serial, "SERIAL")
+    });
+    let port = serial.lock_irq().port();
+    PORT.store(port, Ordering::Relaxed);
+    port
+}
+
+/// Return the port used by SERIAL.
+pub fn port() -> u16 {
+    PORT.load(Ordering::Relaxed)
+}
 
 pub struct Serial {
     port0: Port<u8>,
@@ -14,6 +46,7 @@ pub struct Serial {
     port3: PortWriteOnly<u8>,
     port4: PortWriteOnly<u8>,
     port5: Port<u8>,
+    port: u16,
     initialized: bool,
 }
 
@@ -26,6 +59,7 @@ impl Serial {
             port3: PortWriteOnly::new(port + 3),
             port4: PortWriteOnly::new(port + 4),
             port5: Port::new(port + 5),
+            port,
             initialized: false,
         }
     }
@@ -50,6 +84,10 @@ impl Serial {
         }
     }
 
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
     pub fn write_byte(&mut self, byte: u8) {
         unsafe {
             while self.port5.read() & 0x20 == 0 {
@@ -92,8 +130,5 @@ macro_rules! kdebugln {
 #[doc(hidden)]
 pub fn _debug(args: fmt::Arguments) {
     use core::fmt::Write;
-    // TODO Remove interupt during lock to avoid dead lock.
-    unsafe {
-        SERIAL.lock().write_fmt(args).unwrap();
-    }
+    SERIAL.lock_irq().write_fmt(args).unwrap();
 }
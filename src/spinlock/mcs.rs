@@ -0,0 +1,230 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use super::{enable_interrupts, save_and_disable_interrupts};
+
+/// Maximum number of simultaneous holders and waiters of one lock.
+const MCS_NODES: usize = 8;
+
+/// Node index meaning no node, indexes are stored plus one.
+const NONE: usize = 0;
+
+/// Queue entry of a waiter, each waiter spins on its own node.
+#[derive(Debug)]
+struct McsNode {
+    used: AtomicBool,
+    waiting: AtomicBool,
+    next: AtomicUsize,
+}
+
+impl McsNode {
+    const fn new() -> Self {
+        Self {
+            used: AtomicBool::new(false),
+            waiting: AtomicBool::new(false),
+            next: AtomicUsize::new(NONE),
+        }
+    }
+}
+
+/// Queued (MCS) lock: waiters are served in order and each one spins on
+/// its own node instead of the shared lock word.
+///
+/// The nodes come from a pool inside the lock so the guard API is the
+/// same as `Spinlock`.
+#[derive(Debug)]
+pub struct McsLock<T> {
+    tail: AtomicUsize,
+    nodes: [McsNode; MCS_NODES],
+    name: &'static str,
+    data: UnsafeCell<T>,
+}
+
+/// A guard to which the protected data can be accessed
+///
+/// When the guard falls out of scope it will release the lock.
+#[derive(Debug)]
+pub struct McsLockGuard<'a, T: 'a> {
+    lock: &'a McsLock<T>,
+    node: usize,
+    data: &'a mut T,
+    restore_interrupts: bool,
+}
+
+unsafe impl<T> Sync for McsLock<T> {}
+
+impl<T> McsLock<T> {
+    /// Create new McsLock wrapping the supplied data.
+    pub const fn new(d: T) -> Self {
+        Self::with_name(d, "unnamed")
+    }
+
+    /// Create new McsLock with a name used in the diagnostic messages.
+    pub const fn with_name(d: T, name: &'static str) -> Self {
+        Self {
+            tail: AtomicUsize::new(NONE),
+            nodes: [
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+                McsNode::new(),
+            ],
+            name,
+            data: UnsafeCell::new(d),
+        }
+    }
+
+    /// Return the name of the lock.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn node(&self, index: usize) -> &McsNode {
+        &self.nodes[index - 1]
+    }
+
+    /// Take a free node from the pool, wait if they are all used.
+    fn claim_node(&self) -> usize {
+        loop {
+            if let Some(index) = self.try_claim_node() {
+                return index;
+            }
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Take a free node from the pool, return None if they are all used.
+    fn try_claim_node(&self) -> Option<usize> {
+        for (i, node) in self.nodes.iter().enumerate() {
+            if node
+                .used
+                .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_ok()
+            {
+                node.next.store(NONE, Ordering::Relaxed);
+                node.waiting.store(true, Ordering::Relaxed);
+                return Some(i + 1);
+            }
+        }
+        None
+    }
+
+    fn release_node(&self, index: usize) {
+        self.node(index).used.store(false, Ordering::Release);
+    }
+
+    /// Queue a node and block until it's at the head of the queue.
+    fn obtain_lock(&self) -> usize {
+        let index = self.claim_node();
+        let prev = self.tail.swap(index, Ordering::AcqRel);
+        if prev != NONE {
+            self.node(prev).next.store(index, Ordering::Release);
+            while self.node(index).waiting.load(Ordering::Acquire) {
+                core::hint::spin_loop();
+            }
+        }
+        index
+    }
+
+    /// Return true if the lock is currently held.
+    pub fn is_locked(&self) -> bool {
+        self.tail.load(Ordering::Relaxed) != NONE
+    }
+
+    fn guard(&self, node: usize, restore_interrupts: bool) -> McsLockGuard<'_, T> {
+        McsLockGuard {
+            lock: self,
+            node,
+            data: unsafe { &mut *self.data.get() },
+            restore_interrupts,
+        }
+    }
+
+    /// Locks the MCS lock and return a guard.
+    ///
+    /// The returned value may be dereferenced for data access
+    /// and the lock will be dropped when the guard falls out of scope.
+    pub fn lock(&self) -> McsLockGuard<'_, T> {
+        let node = self.obtain_lock();
+        self.guard(node, false)
+    }
+
+    /// Locks the MCS lock with the interrupts disabled and return a guard.
+    ///
+    /// The interrupt flag is restored to its previous state when the guard
+    /// falls out of scope.
+    pub fn lock_irq(&self) -> McsLockGuard<'_, T> {
+        let restore_interrupts = save_and_disable_interrupts();
+        let node = self.obtain_lock();
+        self.guard(node, restore_interrupts)
+    }
+
+    /// Try to lock the MCS lock without waiting.
+    ///
+    /// Return None if the lock is already held or if all the nodes of the
+    /// pool are used by waiters.
+    pub fn try_lock(&self) -> Option<McsLockGuard<'_, T>> {
+        if self.is_locked() {
+            return None;
+        }
+        let node = self.try_claim_node()?;
+        match self
+            .tail
+            .compare_exchange(NONE, node, Ordering::AcqRel, Ordering::Relaxed)
+        {
+            Ok(_) => Some(self.guard(node, false)),
+            Err(_) => {
+                self.release_node(node);
+                None
+            }
+        }
+    }
+}
+
+impl<'a, T> Deref for McsLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &*self.data
+    }
+}
+
+impl<'a, T> DerefMut for McsLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.data
+    }
+}
+
+impl<'a, T> Drop for McsLockGuard<'a, T> {
+    /// Hand the lock to the next waiter, or leave it free if there is none.
+    fn drop(&mut self) {
+        let lock = self.lock;
+        let node = lock.node(self.node);
+        if node.next.load(Ordering::Acquire) == NONE
+            && lock
+                .tail
+                .compare_exchange(self.node, NONE, Ordering::AcqRel, Ordering::Relaxed)
+                .is_ok()
+        {
+            lock.release_node(self.node);
+        } else {
+            // A waiter swapped the tail, wait until it links itself.
+            let mut next = node.next.load(Ordering::Acquire);
+            while next == NONE {
+                core::hint::spin_loop();
+                next = node.next.load(Ordering::Acquire);
+            }
+            lock.node(next).waiting.store(false, Ordering::Release);
+            lock.release_node(self.node);
+        }
+        if self.restore_interrupts {
+            enable_interrupts();
+        }
+    }
+}
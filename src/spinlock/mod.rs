@@ -1,13 +1,42 @@
 use core::{
+    arch::asm,
     cell::UnsafeCell,
     ops::{Deref, DerefMut},
     sync::atomic::{AtomicBool, Ordering},
 };
 
+#[cfg(feature = "lock_debug")]
+use core::sync::atomic::AtomicUsize;
+
+mod mcs;
+mod ticket;
+pub use self::mcs::{McsLock, McsLockGuard};
+pub use self::ticket::{TicketLock, TicketLockGuard};
+
+/// Lock used by the console devices (WRITER, SERIAL and KEYBOARD),
+/// selected with the `ticket_lock` or `mcs_lock` feature.
+#[cfg(feature = "ticket_lock")]
+pub type ConsoleLock<T> = TicketLock<T>;
+#[cfg(all(feature = "mcs_lock", not(feature = "ticket_lock")))]
+pub type ConsoleLock<T> = McsLock<T>;
+#[cfg(all(feature = "ticket_lock", feature = "mcs_lock"))]
+compile_error!("the ticket_lock and mcs_lock features are exclusive, enable only one");
+#[cfg(not(any(feature = "ticket_lock", feature = "mcs_lock")))]
+pub type ConsoleLock<T> = Spinlock<T>;
+
+/// Number of spins after which a waiter reports a possible deadlock.
+#[cfg(feature = "lock_debug")]
+pub static SPIN_LIMIT: AtomicUsize = AtomicUsize::new(10_000_000);
+
 /// Wrapper of a data in thread-safe manner.
 #[derive(Debug)]
 pub struct Spinlock<T> {
     lock: AtomicBool,
+    name: &'static str,
+    #[cfg(feature = "lock_debug")]
+    owner: AtomicUsize,
+    #[cfg(feature = "lock_debug")]
+    contention: AtomicUsize,
     data: UnsafeCell<T>,
 }
 
@@ -16,8 +45,27 @@ pub struct Spinlock<T> {
 /// When the guard falls out of scope it will release the lock.
 #[derive(Debug)]
 pub struct SpinlockGuard<'a, T: 'a> {
-    lock: &'a AtomicBool,
+    spinlock: &'a Spinlock<T>,
     data: &'a mut T,
+    restore_interrupts: bool,
+}
+
+const EFLAGS_IF: u32 = 1 << 9;
+
+/// Disable the interrupts and return true if they were enabled.
+fn save_and_disable_interrupts() -> bool {
+    let eflags: u32;
+    unsafe {
+        asm!("pushfd", "pop {}", "cli", out(reg) eflags, options(nomem));
+    }
+    eflags & EFLAGS_IF != 0
+}
+
+/// Enable the interrupts.
+fn enable_interrupts() {
+    unsafe {
+        asm!("sti", options(nomem, nostack));
+    }
 }
 
 unsafe impl<T> Sync for Spinlock<T> {}
@@ -25,41 +73,140 @@ unsafe impl<T> Sync for Spinlock<T> {}
 impl<T> Spinlock<T> {
     /// Create new SpinnLock wrapping the supplied data.
     pub const fn new(d: T) -> Self {
+        Self::with_name(d, "unnamed")
+    }
+
+    /// Create new SpinnLock with a name used in the diagnostic messages.
+    pub const fn with_name(d: T, name: &'static str) -> Self {
         Self {
             lock: AtomicBool::new(false),
+            name,
+            #[cfg(feature = "lock_debug")]
+            owner: AtomicUsize::new(0),
+            #[cfg(feature = "lock_debug")]
+            contention: AtomicUsize::new(0),
             data: UnsafeCell::new(d),
         }
     }
 
+    /// Return the name of the lock.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Try once to take the lock, return true on success.
+    fn acquire(&self) -> bool {
+        self.lock
+            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
     /// Block until it's unlocked.
     fn obtain_lock(&self) {
-        while self
-            .lock
-            .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
-            .is_err()
-        {
+        #[cfg(feature = "lock_debug")]
+        let mut spins = 0;
+        while !self.acquire() {
+            #[cfg(feature = "lock_debug")]
+            if spins == 0 {
+                self.contention.fetch_add(1, Ordering::Relaxed);
+            }
             while self.is_locked() {
                 core::hint::spin_loop();
+                #[cfg(feature = "lock_debug")]
+                {
+                    spins += 1;
+                    if spins % SPIN_LIMIT.load(Ordering::Relaxed).max(1) == 0 {
+                        self.report_deadlock();
+                    }
+                }
             }
         }
     }
 
+    /// Report the lock holder on the serial port.
+    ///
+    /// Write to the port without `SERIAL`, which may be the lock we are
+    /// waiting for.
+    #[cfg(feature = "lock_debug")]
+    fn report_deadlock(&self) {
+        use core::fmt::Write;
+        let mut serial = crate::serial::Serial::new(crate::serial::port());
+        let _ = writeln!(
+            serial,
+            "possible deadlock on {} held by {:#010x}",
+            self.name,
+            self.owner()
+        );
+    }
+
     /// Return true if the lock is currently held.
     pub fn is_locked(&self) -> bool {
         self.lock.load(Ordering::Relaxed)
     }
 
+    /// Return the return address of the function which holds the lock,
+    /// 0 if it's free.
+    #[cfg(feature = "lock_debug")]
+    pub fn owner(&self) -> usize {
+        self.owner.load(Ordering::Relaxed)
+    }
+
+    /// Return how many times the lock was found held by someone else.
+    #[cfg(feature = "lock_debug")]
+    pub fn contention(&self) -> usize {
+        self.contention.load(Ordering::Relaxed)
+    }
+
+    /// Return a guard on the data once the lock is held.
+    ///
+    /// Must be inlined in the public lock functions so the owner is their
+    /// caller.
+    #[inline(always)]
+    fn guard(&self, restore_interrupts: bool) -> SpinlockGuard<'_, T> {
+        #[cfg(feature = "lock_debug")]
+        self.owner
+            .store(crate::backtrace::return_address(0), Ordering::Relaxed);
+        SpinlockGuard {
+            spinlock: self,
+            data: unsafe { &mut *self.data.get() },
+            restore_interrupts,
+        }
+    }
+
     /// Locks the spinlock and return a guard.
     ///
     /// The returned value may be dereferenced for data access
     /// and the lock will be dropped when the guard falls out of scope.
-    pub fn lock(&self) -> SpinlockGuard<T> {
+    #[cfg_attr(feature = "lock_debug", inline(never))]
+    pub fn lock(&self) -> SpinlockGuard<'_, T> {
         self.obtain_lock();
+        self.guard(false)
+    }
 
-        SpinlockGuard {
-            lock: &self.lock,
-            data: unsafe { &mut *self.data.get() },
+    /// Locks the spinlock with the interrupts disabled and return a guard.
+    ///
+    /// The interrupt flag is restored to its previous state when the guard
+    /// falls out of scope, so the lock can be shared with interrupt handlers.
+    #[cfg_attr(feature = "lock_debug", inline(never))]
+    pub fn lock_irq(&self) -> SpinlockGuard<'_, T> {
+        let restore_interrupts = save_and_disable_interrupts();
+        self.obtain_lock();
+        self.guard(restore_interrupts)
+    }
+
+    /// Try to lock the spinlock without waiting.
+    ///
+    /// Return None if the lock is already held.
+    #[cfg_attr(feature = "lock_debug", inline(never))]
+    pub fn try_lock(&self) -> Option<SpinlockGuard<'_, T>> {
+        if self
+            .lock
+            .compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            return None;
         }
+        Some(self.guard(false))
     }
 }
 
@@ -79,6 +226,11 @@ impl<'a, T> DerefMut for SpinlockGuard<'a, T> {
 impl<'a, T> Drop for SpinlockGuard<'a, T> {
     /// The dropping of the SpinlockGuard will release the lock it was created from.
     fn drop(&mut self) {
-        self.lock.store(false, Ordering::SeqCst);
+        #[cfg(feature = "lock_debug")]
+        self.spinlock.owner.store(0, Ordering::Relaxed);
+        self.spinlock.lock.store(false, Ordering::SeqCst);
+        if self.restore_interrupts {
+            enable_interrupts();
+        }
     }
 }
@@ -0,0 +1,130 @@
+use core::{
+    cell::UnsafeCell,
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use super::{enable_interrupts, save_and_disable_interrupts};
+
+/// Wrapper of a data in thread-safe manner, granting the lock in the
+/// order it was asked.
+#[derive(Debug)]
+pub struct TicketLock<T> {
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    name: &'static str,
+    data: UnsafeCell<T>,
+}
+
+/// A guard to which the protected data can be accessed
+///
+/// When the guard falls out of scope it will release the lock.
+#[derive(Debug)]
+pub struct TicketLockGuard<'a, T: 'a> {
+    lock: &'a TicketLock<T>,
+    data: &'a mut T,
+    restore_interrupts: bool,
+}
+
+unsafe impl<T> Sync for TicketLock<T> {}
+
+impl<T> TicketLock<T> {
+    /// Create new TicketLock wrapping the supplied data.
+    pub const fn new(d: T) -> Self {
+        Self::with_name(d, "unnamed")
+    }
+
+    /// Create new TicketLock with a name used in the diagnostic messages.
+    pub const fn with_name(d: T, name: &'static str) -> Self {
+        Self {
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            name,
+            data: UnsafeCell::new(d),
+        }
+    }
+
+    /// Return the name of the lock.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Take a ticket and block until it's served.
+    fn obtain_lock(&self) {
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Return true if the lock is currently held.
+    pub fn is_locked(&self) -> bool {
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+
+    fn guard(&self, restore_interrupts: bool) -> TicketLockGuard<'_, T> {
+        TicketLockGuard {
+            lock: self,
+            data: unsafe { &mut *self.data.get() },
+            restore_interrupts,
+        }
+    }
+
+    /// Locks the ticket lock and return a guard.
+    ///
+    /// The returned value may be dereferenced for data access
+    /// and the lock will be dropped when the guard falls out of scope.
+    pub fn lock(&self) -> TicketLockGuard<'_, T> {
+        self.obtain_lock();
+        self.guard(false)
+    }
+
+    /// Locks the ticket lock with the interrupts disabled and return a guard.
+    ///
+    /// The interrupt flag is restored to its previous state when the guard
+    /// falls out of scope.
+    pub fn lock_irq(&self) -> TicketLockGuard<'_, T> {
+        let restore_interrupts = save_and_disable_interrupts();
+        self.obtain_lock();
+        self.guard(restore_interrupts)
+    }
+
+    /// Try to lock the ticket lock without waiting.
+    ///
+    /// Return None if the lock is already held or has waiters.
+    pub fn try_lock(&self) -> Option<TicketLockGuard<'_, T>> {
+        let serving = self.now_serving.load(Ordering::Relaxed);
+        self.next_ticket
+            .compare_exchange(
+                serving,
+                serving.wrapping_add(1),
+                Ordering::Acquire,
+                Ordering::Relaxed,
+            )
+            .ok()
+            .map(|_| self.guard(false))
+    }
+}
+
+impl<'a, T> Deref for TicketLockGuard<'a, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &*self.data
+    }
+}
+
+impl<'a, T> DerefMut for TicketLockGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut *self.data
+    }
+}
+
+impl<'a, T> Drop for TicketLockGuard<'a, T> {
+    /// Serve the next ticket.
+    fn drop(&mut self) {
+        self.lock.now_serving.fetch_add(1, Ordering::Release);
+        if self.restore_interrupts {
+            enable_interrupts();
+        }
+    }
+}
@@ -0,0 +1,380 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    any::Any,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::{
+    spinlock::Spinlock,
+    vfs::{self, DirEntry, Error, FileSystem, FileType, Inode, InodeRef, Metadata},
+};
+
+const ROOT_INO: usize = 1;
+
+/// Writable filesystem in memory.
+pub struct TmpFs {
+    root: Arc<TmpInode>,
+}
+
+/// Inode numbers and space accounting shared by the inodes of a TmpFs.
+struct Shared {
+    next_ino: AtomicUsize,
+    /// Bytes used by the file contents and the symlink targets.
+    used: Spinlock<usize>,
+    /// Maximum of used, None for no limit.
+    limit: Option<usize>,
+}
+
+enum Content {
+    File(Vec<u8>),
+    Directory(Vec<(String, Arc<TmpInode>)>),
+    Symlink(String),
+}
+
+struct TmpInode {
+    ino: u64,
+    shared: Arc<Shared>,
+    mode: u16,
+    content: Spinlock<Content>,
+}
+
+impl Shared {
+    /// Account for size more bytes, or less if size is negative.
+    fn reserve(&self, size: isize) -> Result<(), Error> {
+        let mut used = self.used.lock();
+        let new = if size < 0 {
+            used.saturating_sub(size.unsigned_abs())
+        } else {
+            used.checked_add(size as usize).ok_or(Error::NoSpace)?
+        };
+        match self.limit {
+            Some(limit) if size > 0 && new > limit => Err(Error::NoSpace),
+            _ => {
+                *used = new;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl TmpFs {
+    /// Create an empty filesystem holding at most limit bytes.
+    pub fn new(limit: Option<usize>) -> Self {
+        let shared = Arc::new(Shared {
+            next_ino: AtomicUsize::new(ROOT_INO),
+            used: Spinlock::with_name(0, "TMPFS"),
+            limit,
+        });
+        Self {
+            root: TmpInode::new(&shared, Content::Directory(Vec::new()), 0o1777),
+        }
+    }
+
+    /// Number of bytes used.
+    pub fn used(&self) -> usize {
+        *self.root.shared.used.lock()
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+
+    fn root(&self) -> Result<InodeRef, Error> {
+        Ok(self.root.clone())
+    }
+}
+
+impl TmpInode {
+    fn new(shared: &Arc<Shared>, content: Content, mode: u16) -> Arc<Self> {
+        Arc::new(Self {
+            ino: shared.next_ino.fetch_add(1, Ordering::Relaxed) as u64,
+            shared: shared.clone(),
+            mode,
+            content: Spinlock::with_name(content, "TMPFS_INODE"),
+        })
+    }
+
+    fn kind(&self) -> FileType {
+        match *self.content.lock() {
+            Content::File(_) => FileType::File,
+            Content::Directory(_) => FileType::Directory,
+            Content::Symlink(_) => FileType::Symlink,
+        }
+    }
+
+    fn is_empty_directory(&self) -> bool {
+        match &*self.content.lock() {
+            Content::Directory(entries) => entries.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// Bytes accounted for the inode.
+    fn size(&self) -> usize {
+        match &*self.content.lock() {
+            Content::File(data) => data.len(),
+            Content::Directory(_) => 0,
+            Content::Symlink(target) => target.len(),
+        }
+    }
+
+    /// Add a new entry to this directory.
+    fn add(&self, name: &str, content: Content, mode: u16) -> Result<InodeRef, Error> {
+        let size = match &content {
+            Content::Symlink(target) => target.len(),
+            _ => 0,
+        };
+        let mut dir = self.content.lock();
+        let entries = match &mut *dir {
+            Content::Directory(entries) => entries,
+            _ => return Err(Error::NotDirectory),
+        };
+        if entries.iter().any(|(n, _)| n == name) {
+            return Err(Error::Exists);
+        }
+        self.shared.reserve(size as isize)?;
+        let inode = TmpInode::new(&self.shared, content, mode);
+        entries.push((name.to_string(), inode.clone()));
+        Ok(inode)
+    }
+
+    /// Check that source can replace the entry target of a rename.
+    fn check_replace(source: &TmpInode, target: &TmpInode) -> Result<(), Error> {
+        match (source.kind(), target.kind()) {
+            (FileType::Directory, FileType::Directory) if !target.is_empty_directory() => {
+                Err(Error::NotEmpty)
+            }
+            (FileType::Directory, FileType::Directory) => Ok(()),
+            (FileType::Directory, _) => Err(Error::NotDirectory),
+            (_, FileType::Directory) => Err(Error::IsDirectory),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Extend data with zeros up to size, failing instead of panicking when
+/// the heap is full.
+fn grow(shared: &Shared, data: &mut Vec<u8>, size: usize) -> Result<(), Error> {
+    data.try_reserve(size - data.len())
+        .map_err(|_| Error::NoSpace)?;
+    shared.reserve((size - data.len()) as isize)?;
+    data.resize(size, 0);
+    Ok(())
+}
+
+fn entries(content: &mut Content) -> Result<&mut Vec<(String, Arc<TmpInode>)>, Error> {
+    match content {
+        Content::Directory(entries) => Ok(entries),
+        _ => Err(Error::NotDirectory),
+    }
+}
+
+impl Inode for TmpInode {
+    fn metadata(&self) -> Result<Metadata, Error> {
+        let content = self.content.lock();
+        let (kind, size, links) = match &*content {
+            Content::File(data) => (FileType::File, data.len(), 1),
+            Content::Directory(entries) => {
+                let subdirs = entries
+                    .iter()
+                    .filter(|(_, inode)| inode.kind() == FileType::Directory)
+                    .count();
+                (FileType::Directory, 0, 2 + subdirs as u32)
+            }
+            Content::Symlink(target) => (FileType::Symlink, target.len(), 1),
+        };
+        Ok(Metadata {
+            ino: self.ino,
+            kind,
+            size: size as u64,
+            links,
+            mode: self.mode,
+        })
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        let content = self.content.lock();
+        let data = match &*content {
+            Content::File(data) => data,
+            Content::Directory(_) => return Err(Error::IsDirectory),
+            Content::Symlink(_) => return Err(Error::InvalidArgument),
+        };
+        if offset >= data.len() as u64 {
+            return Ok(0);
+        }
+        let data = &data[offset as usize..];
+        let count = buf.len().min(data.len());
+        buf[..count].copy_from_slice(&data[..count]);
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> Result<usize, Error> {
+        let mut content = self.content.lock();
+        let data = match &mut *content {
+            Content::File(data) => data,
+            Content::Directory(_) => return Err(Error::IsDirectory),
+            Content::Symlink(_) => return Err(Error::InvalidArgument),
+        };
+        let end = (offset as usize)
+            .checked_add(buf.len())
+            .filter(|_| offset <= usize::MAX as u64)
+            .ok_or(Error::NoSpace)?;
+        if end > data.len() {
+            grow(&self.shared, data, end)?;
+        }
+        data[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, size: u64) -> Result<(), Error> {
+        let mut content = self.content.lock();
+        let data = match &mut *content {
+            Content::File(data) => data,
+            Content::Directory(_) => return Err(Error::IsDirectory),
+            Content::Symlink(_) => return Err(Error::InvalidArgument),
+        };
+        if size > isize::MAX as u64 {
+            return Err(Error::NoSpace);
+        }
+        let size = size as usize;
+        if size > data.len() {
+            return grow(&self.shared, data, size);
+        }
+        self.shared.reserve(size as isize - data.len() as isize)?;
+        data.truncate(size);
+        if data.capacity() > 2 * data.len() {
+            data.shrink_to_fit();
+        }
+        Ok(())
+    }
+
+    fn lookup(&self, name: &str) -> Result<InodeRef, Error> {
+        let mut content = self.content.lock();
+        match entries(&mut content)?.iter().find(|(n, _)| n == name) {
+            Some((_, inode)) => Ok(inode.clone()),
+            None => Err(Error::NotFound),
+        }
+    }
+
+    fn readdir(&self, offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        let mut content = self.content.lock();
+        Ok(entries(&mut content)?
+            .get(offset as usize)
+            .map(|(name, inode)| {
+                let entry = DirEntry {
+                    name: name.clone(),
+                    ino: inode.ino,
+                    kind: inode.kind(),
+                };
+                (entry, offset + 1)
+            }))
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> Result<InodeRef, Error> {
+        match kind {
+            FileType::File => self.add(name, Content::File(Vec::new()), 0o644),
+            FileType::Directory => self.add(name, Content::Directory(Vec::new()), 0o755),
+            _ => Err(Error::NotSupported),
+        }
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<InodeRef, Error> {
+        self.add(name, Content::Symlink(target.to_string()), 0o777)
+    }
+
+    fn unlink(&self, name: &str) -> Result<(), Error> {
+        let mut content = self.content.lock();
+        let entries = entries(&mut content)?;
+        let index = match entries.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => return Err(Error::NotFound),
+        };
+        let inode = &entries[index].1;
+        if inode.kind() == FileType::Directory && !inode.is_empty_directory() {
+            return Err(Error::NotEmpty);
+        }
+        // An open file keeps its content, but it's no longer reachable.
+        self.shared.reserve(-(inode.size() as isize))?;
+        entries.remove(index);
+        Ok(())
+    }
+
+    fn rename(&self, name: &str, new_dir: &InodeRef, new_name: &str) -> Result<(), Error> {
+        let new_dir = match new_dir.as_any().downcast_ref::<TmpInode>() {
+            Some(dir) if Arc::ptr_eq(&dir.shared, &self.shared) => dir,
+            _ => return Err(Error::CrossDevice),
+        };
+        let mut content = self.content.lock();
+        let entries = entries(&mut content)?;
+        let index = match entries.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => return Err(Error::NotFound),
+        };
+        let source = entries[index].1.clone();
+        if core::ptr::eq(self, new_dir) {
+            if name == new_name {
+                return Ok(());
+            }
+            if let Some(target) = entries.iter().position(|(n, _)| n == new_name) {
+                TmpInode::check_replace(&source, &entries[target].1)?;
+                self.shared.reserve(-(entries[target].1.size() as isize))?;
+                entries.remove(target);
+            }
+            let index = entries.iter().position(|(n, _)| n == name).unwrap();
+            entries[index].0 = new_name.to_string();
+        } else {
+            let mut new_content = new_dir.content.lock();
+            let targets = self::entries(&mut new_content)?;
+            if let Some(target) = targets.iter().position(|(n, _)| n == new_name) {
+                TmpInode::check_replace(&source, &targets[target].1)?;
+                self.shared.reserve(-(targets[target].1.size() as isize))?;
+                targets.remove(target);
+            }
+            targets.push((new_name.to_string(), source));
+            entries.remove(index);
+        }
+        Ok(())
+    }
+
+    fn readlink(&self) -> Result<String, Error> {
+        match &*self.content.lock() {
+            Content::Symlink(target) => Ok(target.clone()),
+            _ => Err(Error::InvalidArgument),
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+}
+
+/// Parse a size with an optional K, M or G suffix.
+pub fn parse_size(s: &str) -> Option<usize> {
+    let (digits, unit) = match s.as_bytes().last()? {
+        b'k' | b'K' => (&s[..s.len() - 1], 1024),
+        b'm' | b'M' => (&s[..s.len() - 1], 1024 * 1024),
+        b'g' | b'G' => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    digits.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Mount a tmpfs on `/` when there is no root filesystem, and on `/tmp`
+/// if the root has this directory.
+pub fn init() {
+    let path = if vfs::mounts().is_empty() {
+        "/"
+    } else {
+        match vfs::stat("/tmp") {
+            Ok(metadata) if metadata.kind == FileType::Directory => "/tmp",
+            _ => return,
+        }
+    };
+    let _ = vfs::mount("tmpfs", path, Arc::new(TmpFs::new(None)));
+}
@@ -0,0 +1,227 @@
+use alloc::{string::String, vec::Vec};
+use core::ops::BitOr;
+
+use super::{mount, resolve, resolve_parent, DirEntry, Error, FileType, InodeRef, Metadata};
+use crate::spinlock::Spinlock;
+
+/// Maximum number of open files of a table.
+const MAX_FILES: usize = 64;
+
+/// File descriptors of the kernel, until there are processes to own
+/// their own table.
+static FILES: Spinlock<FileTable> = Spinlock::with_name(FileTable::new(), "FILES");
+
+/// Mode of an open file.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct OpenFlags(u32);
+
+impl OpenFlags {
+    pub const READ: OpenFlags = OpenFlags(1 << 0);
+    pub const WRITE: OpenFlags = OpenFlags(1 << 1);
+    /// Create the file if it doesn't exist.
+    pub const CREATE: OpenFlags = OpenFlags(1 << 2);
+    /// Empty the file when opened for writing.
+    pub const TRUNCATE: OpenFlags = OpenFlags(1 << 3);
+    /// Write at the end of the file.
+    pub const APPEND: OpenFlags = OpenFlags(1 << 4);
+
+    pub fn contains(self, other: OpenFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for OpenFlags {
+    type Output = OpenFlags;
+    fn bitor(self, other: OpenFlags) -> OpenFlags {
+        OpenFlags(self.0 | other.0)
+    }
+}
+
+/// Origin of a seek.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum SeekFrom {
+    Start(u64),
+    Current(i64),
+    End(i64),
+}
+
+/// An open file.
+struct File {
+    /// Absolute path at the time of the open, to find the files of a mount.
+    path: String,
+    inode: InodeRef,
+    flags: OpenFlags,
+    /// Byte offset for a file, readdir position for a directory.
+    offset: u64,
+}
+
+/// Table of the open files, indexed by file descriptor.
+pub struct FileTable {
+    files: Vec<Option<File>>,
+}
+
+impl Default for FileTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileTable {
+    pub const fn new() -> Self {
+        Self { files: Vec::new() }
+    }
+
+    fn get(&mut self, fd: usize) -> Result<&mut File, Error> {
+        match self.files.get_mut(fd) {
+            Some(Some(file)) => Ok(file),
+            _ => Err(Error::BadDescriptor),
+        }
+    }
+
+    /// Open the file at path and return the lowest free descriptor.
+    pub fn open(&mut self, path: &str, flags: OpenFlags) -> Result<usize, Error> {
+        let fd = match self.files.iter().position(|f| f.is_none()) {
+            Some(fd) => fd,
+            None if self.files.len() < MAX_FILES => {
+                self.files.push(None);
+                self.files.len() - 1
+            }
+            None => return Err(Error::TooManyFiles),
+        };
+        let dentry = match resolve(path, true) {
+            Err(Error::NotFound) if flags.contains(OpenFlags::CREATE) => {
+                let (parent, name) = resolve_parent(path)?;
+                parent.inode.create(&name, FileType::File)?;
+                resolve(path, true)?
+            }
+            result => result?,
+        };
+        let metadata = dentry.metadata()?;
+        let writing = flags.contains(OpenFlags::WRITE);
+        if writing && metadata.kind == FileType::Directory {
+            return Err(Error::IsDirectory);
+        }
+        if writing && flags.contains(OpenFlags::TRUNCATE) && metadata.size != 0 {
+            dentry.inode.truncate(0)?;
+        }
+        self.files[fd] = Some(File {
+            path: dentry.path,
+            inode: dentry.inode,
+            flags,
+            offset: 0,
+        });
+        Ok(fd)
+    }
+
+    /// Read from the offset of the file and advance it.
+    pub fn read(&mut self, fd: usize, buf: &mut [u8]) -> Result<usize, Error> {
+        let file = self.get(fd)?;
+        if !file.flags.contains(OpenFlags::READ) {
+            return Err(Error::BadDescriptor);
+        }
+        if file.inode.metadata()?.kind == FileType::Directory {
+            return Err(Error::IsDirectory);
+        }
+        let count = file.inode.read_at(file.offset, buf)?;
+        file.offset += count as u64;
+        Ok(count)
+    }
+
+    /// Write at the offset of the file, or at its end in append mode, and
+    /// advance it.
+    pub fn write(&mut self, fd: usize, buf: &[u8]) -> Result<usize, Error> {
+        let file = self.get(fd)?;
+        if !file.flags.contains(OpenFlags::WRITE) {
+            return Err(Error::BadDescriptor);
+        }
+        if file.flags.contains(OpenFlags::APPEND) {
+            file.offset = file.inode.metadata()?.size;
+        }
+        let count = file.inode.write_at(file.offset, buf)?;
+        file.offset += count as u64;
+        Ok(count)
+    }
+
+    /// Move the offset of the file and return it.
+    pub fn seek(&mut self, fd: usize, pos: SeekFrom) -> Result<u64, Error> {
+        let file = self.get(fd)?;
+        let (base, delta) = match pos {
+            SeekFrom::Start(offset) => (offset, 0),
+            SeekFrom::Current(delta) => (file.offset, delta),
+            SeekFrom::End(delta) => (file.inode.metadata()?.size, delta),
+        };
+        let offset = if delta < 0 {
+            base.checked_sub(delta.unsigned_abs())
+        } else {
+            base.checked_add(delta as u64)
+        };
+        file.offset = offset.ok_or(Error::InvalidArgument)?;
+        Ok(file.offset)
+    }
+
+    pub fn close(&mut self, fd: usize) -> Result<(), Error> {
+        match self.files.get_mut(fd).and_then(|f| f.take()) {
+            Some(_) => Ok(()),
+            None => Err(Error::BadDescriptor),
+        }
+    }
+
+    pub fn fstat(&mut self, fd: usize) -> Result<Metadata, Error> {
+        self.get(fd)?.inode.metadata()
+    }
+
+    /// Return the next entry of an open directory, None at the end.
+    pub fn readdir(&mut self, fd: usize) -> Result<Option<DirEntry>, Error> {
+        let file = self.get(fd)?;
+        match file.inode.readdir(file.offset)? {
+            Some((entry, next)) => {
+                file.offset = next;
+                Ok(Some(entry))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Return true if a file below the directory path is open.
+    fn is_open_below(&self, path: &str) -> bool {
+        self.files
+            .iter()
+            .flatten()
+            .any(|f| mount::is_below(&mount::mount_point_of(&f.path), path))
+    }
+}
+
+/// Open the file at path and return its descriptor.
+pub fn open(path: &str, flags: OpenFlags) -> Result<usize, Error> {
+    FILES.lock().open(path, flags)
+}
+
+pub fn read(fd: usize, buf: &mut [u8]) -> Result<usize, Error> {
+    FILES.lock().read(fd, buf)
+}
+
+pub fn write(fd: usize, buf: &[u8]) -> Result<usize, Error> {
+    FILES.lock().write(fd, buf)
+}
+
+pub fn seek(fd: usize, pos: SeekFrom) -> Result<u64, Error> {
+    FILES.lock().seek(fd, pos)
+}
+
+pub fn close(fd: usize) -> Result<(), Error> {
+    FILES.lock().close(fd)
+}
+
+pub fn fstat(fd: usize) -> Result<Metadata, Error> {
+    FILES.lock().fstat(fd)
+}
+
+pub fn readdir(fd: usize) -> Result<Option<DirEntry>, Error> {
+    FILES.lock().readdir(fd)
+}
+
+/// Return true if a file of the filesystem mounted on path, or of one
+/// mounted below it, is open.
+pub(super) fn is_open_below(path: &str) -> bool {
+    FILES.lock().is_open_below(path)
+}
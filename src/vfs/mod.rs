@@ -0,0 +1,444 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{any::Any, fmt};
+
+use crate::{block, spinlock::Spinlock};
+
+mod file;
+mod mount;
+
+pub use self::file::{
+    close, fstat, open, read, readdir, seek, write, FileTable, OpenFlags, SeekFrom,
+};
+pub use self::mount::{mount, mounts, umount, MountInfo};
+
+/// Maximum length of a file name.
+pub const NAME_MAX: usize = 255;
+
+/// Maximum number of symbolic links followed by one lookup.
+const MAX_SYMLINKS: usize = 8;
+
+/// Working directory used to resolve the relative paths, empty for `/`.
+static CWD: Spinlock<String> = Spinlock::with_name(String::new(), "CWD");
+
+/// Indicates differente error condition of a file operation.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Error {
+    NotFound,
+    NotDirectory,
+    IsDirectory,
+    Exists,
+    /// The directory to remove still has entries.
+    NotEmpty,
+    ReadOnly,
+    /// The filesystem doesn't implement the operation.
+    NotSupported,
+    InvalidArgument,
+    NameTooLong,
+    /// Too many symbolic links in a path.
+    Loop,
+    /// The target is a mount point or a mounted filesystem is in use.
+    Busy,
+    /// Rename between two filesystems.
+    CrossDevice,
+    NoSpace,
+    /// The file descriptor isn't open or not for this access.
+    BadDescriptor,
+    /// The file descriptor table is full.
+    TooManyFiles,
+    /// Inconsistent on-disk structures.
+    Corrupted,
+    Device(block::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::NotFound => write!(f, "no such file or directory"),
+            Error::NotDirectory => write!(f, "not a directory"),
+            Error::IsDirectory => write!(f, "is a directory"),
+            Error::Exists => write!(f, "file exists"),
+            Error::NotEmpty => write!(f, "directory not empty"),
+            Error::ReadOnly => write!(f, "read-only filesystem"),
+            Error::NotSupported => write!(f, "operation not supported"),
+            Error::InvalidArgument => write!(f, "invalid argument"),
+            Error::NameTooLong => write!(f, "file name too long"),
+            Error::Loop => write!(f, "too many levels of symbolic links"),
+            Error::Busy => write!(f, "device or resource busy"),
+            Error::CrossDevice => write!(f, "cross-device link"),
+            Error::NoSpace => write!(f, "no space left on device"),
+            Error::BadDescriptor => write!(f, "bad file descriptor"),
+            Error::TooManyFiles => write!(f, "too many open files"),
+            Error::Corrupted => write!(f, "corrupted filesystem"),
+            Error::Device(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<block::Error> for Error {
+    fn from(e: block::Error) -> Self {
+        Error::Device(e)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum FileType {
+    File,
+    Directory,
+    Symlink,
+    CharDevice,
+    BlockDevice,
+    Fifo,
+    Socket,
+}
+
+impl FileType {
+    /// Character used by `ls -l` for the type.
+    pub fn as_char(self) -> char {
+        match self {
+            FileType::File => '-',
+            FileType::Directory => 'd',
+            FileType::Symlink => 'l',
+            FileType::CharDevice => 'c',
+            FileType::BlockDevice => 'b',
+            FileType::Fifo => 'p',
+            FileType::Socket => 's',
+        }
+    }
+}
+
+/// Attributes of an inode.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub struct Metadata {
+    /// Inode number, unique in its filesystem.
+    pub ino: u64,
+    pub kind: FileType,
+    /// Size in bytes, the length of the target for a symlink.
+    pub size: u64,
+    /// Number of hard links.
+    pub links: u32,
+    /// Permission bits (0o755...).
+    pub mode: u16,
+}
+
+/// An entry of a directory.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub ino: u64,
+    pub kind: FileType,
+}
+
+pub type InodeRef = Arc<dyn Inode>;
+
+/// A mounted filesystem.
+pub trait FileSystem: Send + Sync {
+    /// Type of the filesystem (ext2, tmpfs...).
+    fn name(&self) -> &'static str;
+
+    /// Root directory of the filesystem.
+    fn root(&self) -> Result<InodeRef, Error>;
+
+    /// Write back the cached data to the device.
+    fn sync(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A file, directory or symlink of a filesystem.
+///
+/// The directory operations are never called with `.` or `..`, these are
+/// handled by the path resolution so that they can cross mount points.
+pub trait Inode: Send + Sync {
+    fn metadata(&self) -> Result<Metadata, Error>;
+
+    /// Read from offset, return the number of bytes read, 0 at the end.
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> Result<usize, Error> {
+        Err(Error::NotSupported)
+    }
+
+    /// Write at offset, extending the file if needed.
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> Result<usize, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Set the size of the file, the new bytes read as zero.
+    fn truncate(&self, _size: u64) -> Result<(), Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Find the entry name in this directory.
+    fn lookup(&self, _name: &str) -> Result<InodeRef, Error> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Return the entry at the position `offset` of the directory and the
+    /// position of the next entry, None at the end.
+    ///
+    /// The positions are chosen by the filesystem, the first one is 0.
+    fn readdir(&self, _offset: u64) -> Result<Option<(DirEntry, u64)>, Error> {
+        Err(Error::NotDirectory)
+    }
+
+    /// Create an empty file or directory in this directory.
+    fn create(&self, _name: &str, _kind: FileType) -> Result<InodeRef, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Create a symbolic link to target in this directory.
+    fn symlink(&self, _name: &str, _target: &str) -> Result<InodeRef, Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Remove the entry name, a directory must be empty.
+    fn unlink(&self, _name: &str) -> Result<(), Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Move the entry name to new_name in new_dir, replacing the file
+    /// already there. new_dir is of the same filesystem.
+    fn rename(&self, _name: &str, _new_dir: &InodeRef, _new_name: &str) -> Result<(), Error> {
+        Err(Error::ReadOnly)
+    }
+
+    /// Return the target of a symbolic link.
+    fn readlink(&self) -> Result<String, Error> {
+        Err(Error::InvalidArgument)
+    }
+
+    /// Used to get the concrete inode of new_dir in rename.
+    fn as_any(&self) -> &dyn Any;
+}
+
+/// A resolved path: an inode and its absolute path without `.`, `..`
+/// or symbolic links.
+#[derive(Clone)]
+pub struct Dentry {
+    path: String,
+    inode: InodeRef,
+}
+
+impl Dentry {
+    pub fn path(&self) -> &str {
+        if self.path.is_empty() {
+            "/"
+        } else {
+            &self.path
+        }
+    }
+
+    pub fn inode(&self) -> &InodeRef {
+        &self.inode
+    }
+
+    pub fn metadata(&self) -> Result<Metadata, Error> {
+        self.inode.metadata()
+    }
+}
+
+impl fmt::Debug for Dentry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Dentry({})", self.path())
+    }
+}
+
+/// Components of a path, without the empty ones.
+fn components(path: &str) -> impl DoubleEndedIterator<Item = &str> {
+    path.split('/').filter(|c| !c.is_empty())
+}
+
+/// Join the resolved names into an absolute path, empty for the root.
+fn join(stack: &[(String, InodeRef)]) -> String {
+    let mut path = String::new();
+    for (name, _) in stack {
+        path.push('/');
+        path.push_str(name);
+    }
+    path
+}
+
+/// Resolve path from the working directory.
+///
+/// The symbolic links are followed, the last component only if follow is
+/// set.
+fn resolve(path: &str, follow: bool) -> Result<Dentry, Error> {
+    if path.is_empty() {
+        return Err(Error::NotFound);
+    }
+    let root = mount::root()?;
+    let mut pending: Vec<String> = components(path).rev().map(|c| c.to_string()).collect();
+    if !path.starts_with('/') {
+        pending.extend(components(&CWD.lock()).rev().map(|c| c.to_string()));
+    }
+    let mut stack: Vec<(String, InodeRef)> = Vec::new();
+    let mut links = 0;
+    while let Some(name) = pending.pop() {
+        match name.as_str() {
+            "." => continue,
+            ".." => {
+                stack.pop();
+                continue;
+            }
+            _ if name.len() > NAME_MAX => return Err(Error::NameTooLong),
+            _ => {}
+        }
+        let dir = stack.last().map_or(&root, |(_, inode)| inode);
+        if dir.metadata()?.kind != FileType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        let mut inode = dir.lookup(&name)?;
+        let mut path = join(&stack);
+        path.push('/');
+        path.push_str(&name);
+        if let Some(mounted) = mount::root_at(&path) {
+            inode = mounted;
+        }
+        if (follow || !pending.is_empty()) && inode.metadata()?.kind == FileType::Symlink {
+            links += 1;
+            if links > MAX_SYMLINKS {
+                return Err(Error::Loop);
+            }
+            let target = inode.readlink()?;
+            if target.starts_with('/') {
+                stack.clear();
+            }
+            pending.extend(components(&target).rev().map(|c| c.to_string()));
+            continue;
+        }
+        stack.push((name, inode));
+    }
+    Ok(Dentry {
+        path: join(&stack),
+        inode: stack.pop().map_or(root, |(_, inode)| inode),
+    })
+}
+
+/// Resolve the directory of path and return it with the last component.
+fn resolve_parent(path: &str) -> Result<(Dentry, String), Error> {
+    let trimmed = path.trim_end_matches('/');
+    let (dir, name) = match trimmed.rfind('/') {
+        Some(0) => ("/", &trimmed[1..]),
+        Some(i) => (&trimmed[..i], &trimmed[i + 1..]),
+        None => (".", trimmed),
+    };
+    match name {
+        "" | "." | ".." => return Err(Error::InvalidArgument),
+        _ if name.len() > NAME_MAX => return Err(Error::NameTooLong),
+        _ => {}
+    }
+    let parent = resolve(dir, true)?;
+    if parent.metadata()?.kind != FileType::Directory {
+        return Err(Error::NotDirectory);
+    }
+    Ok((parent, name.to_string()))
+}
+
+/// Resolve path, following the symbolic links.
+pub fn lookup(path: &str) -> Result<Dentry, Error> {
+    resolve(path, true)
+}
+
+/// Return the attributes of the file at path.
+pub fn stat(path: &str) -> Result<Metadata, Error> {
+    resolve(path, true)?.metadata()
+}
+
+/// Return the attributes of the file at path, not following a symbolic
+/// link at the end.
+pub fn lstat(path: &str) -> Result<Metadata, Error> {
+    resolve(path, false)?.metadata()
+}
+
+/// Return the target of the symbolic link at path.
+pub fn readlink(path: &str) -> Result<String, Error> {
+    resolve(path, false)?.inode.readlink()
+}
+
+/// Set the size of the file at path.
+pub fn truncate(path: &str, size: u64) -> Result<(), Error> {
+    let file = resolve(path, true)?;
+    if file.metadata()?.kind == FileType::Directory {
+        return Err(Error::IsDirectory);
+    }
+    file.inode.truncate(size)
+}
+
+pub fn mkdir(path: &str) -> Result<(), Error> {
+    let (parent, name) = resolve_parent(path)?;
+    parent.inode.create(&name, FileType::Directory).map(|_| ())
+}
+
+/// Create a symbolic link at path pointing to target.
+pub fn symlink(target: &str, path: &str) -> Result<(), Error> {
+    if target.is_empty() {
+        return Err(Error::NotFound);
+    }
+    let (parent, name) = resolve_parent(path)?;
+    parent.inode.symlink(&name, target).map(|_| ())
+}
+
+/// Remove a file, a symbolic link or an empty directory.
+pub fn unlink(path: &str) -> Result<(), Error> {
+    let (parent, name) = resolve_parent(path)?;
+    let target = resolve(path, false)?;
+    if mount::is_mount_point(&target.path) {
+        return Err(Error::Busy);
+    }
+    parent.inode.unlink(&name)
+}
+
+/// Move the file at old to new, replacing the file at new.
+pub fn rename(old: &str, new: &str) -> Result<(), Error> {
+    let (old_parent, old_name) = resolve_parent(old)?;
+    let (new_parent, new_name) = resolve_parent(new)?;
+    let source = resolve(old, false)?;
+    if mount::is_mount_point(&source.path) {
+        return Err(Error::Busy);
+    }
+    if mount::mount_point_of(&old_parent.path) != mount::mount_point_of(&new_parent.path) {
+        return Err(Error::CrossDevice);
+    }
+    let mut destination = new_parent.path.clone();
+    destination.push('/');
+    destination.push_str(&new_name);
+    if destination == source.path {
+        return Ok(());
+    }
+    if mount::is_mount_point(&destination) {
+        return Err(Error::Busy);
+    }
+    // A directory can't be moved below itself, nor replace one of its
+    // parents which are not empty.
+    if mount::is_below(&destination, &source.path) {
+        return Err(Error::InvalidArgument);
+    }
+    if mount::is_below(&source.path, &destination) {
+        return Err(Error::NotEmpty);
+    }
+    old_parent
+        .inode
+        .rename(&old_name, &new_parent.inode, &new_name)
+}
+
+/// Change the working directory.
+pub fn chdir(path: &str) -> Result<(), Error> {
+    let dir = resolve(path, true)?;
+    if dir.metadata()?.kind != FileType::Directory {
+        return Err(Error::NotDirectory);
+    }
+    *CWD.lock() = dir.path;
+    Ok(())
+}
+
+/// Return the working directory.
+pub fn cwd() -> String {
+    let cwd = CWD.lock();
+    if cwd.is_empty() {
+        "/".to_string()
+    } else {
+        cwd.clone()
+    }
+}
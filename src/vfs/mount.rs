@@ -0,0 +1,132 @@
+use alloc::{
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+
+use super::{file, resolve, Error, FileSystem, FileType, InodeRef};
+use crate::spinlock::Spinlock;
+
+/// A filesystem attached to a directory.
+struct Mount {
+    /// Absolute path of the mount point, empty for the root.
+    path: String,
+    /// Device or name the filesystem was mounted from.
+    source: String,
+    fs: Arc<dyn FileSystem>,
+    root: InodeRef,
+}
+
+/// Description of a mounted filesystem.
+#[derive(Debug, Clone)]
+pub struct MountInfo {
+    pub path: String,
+    pub source: String,
+    pub fstype: &'static str,
+}
+
+static MOUNTS: Spinlock<Vec<Mount>> = Spinlock::with_name(Vec::new(), "MOUNTS");
+
+/// Return the root directory of the root filesystem.
+pub(super) fn root() -> Result<InodeRef, Error> {
+    root_at("").ok_or(Error::NotFound)
+}
+
+/// Return the root of the filesystem mounted on path.
+///
+/// When several filesystems are mounted on the same path, the last one
+/// hides the others.
+pub(super) fn root_at(path: &str) -> Option<InodeRef> {
+    MOUNTS
+        .lock()
+        .iter()
+        .rev()
+        .find(|m| m.path == path)
+        .map(|m| m.root.clone())
+}
+
+pub(super) fn is_mount_point(path: &str) -> bool {
+    MOUNTS.lock().iter().any(|m| m.path == path)
+}
+
+/// Return true if path is below the mount point (or is the mount point).
+pub(super) fn is_below(path: &str, mount_point: &str) -> bool {
+    path.starts_with(mount_point)
+        && (path.len() == mount_point.len() || path.as_bytes()[mount_point.len()] == b'/')
+}
+
+/// Return the mount point of the filesystem holding path.
+pub(super) fn mount_point_of(path: &str) -> String {
+    MOUNTS
+        .lock()
+        .iter()
+        .filter(|m| is_below(path, &m.path))
+        .max_by_key(|m| m.path.len())
+        .map_or(String::new(), |m| m.path.clone())
+}
+
+/// Attach fs to the directory path, source is only informative.
+///
+/// The first filesystem must be mounted on `/`.
+pub fn mount(source: &str, path: &str, fs: Arc<dyn FileSystem>) -> Result<(), Error> {
+    let root = fs.root()?;
+    let path = if MOUNTS.lock().is_empty() {
+        if super::components(path).next().is_some() || !path.starts_with('/') {
+            return Err(Error::NotFound);
+        }
+        String::new()
+    } else {
+        let dir = resolve(path, true)?;
+        if dir.metadata()?.kind != FileType::Directory {
+            return Err(Error::NotDirectory);
+        }
+        dir.path
+    };
+    MOUNTS.lock().push(Mount {
+        path,
+        source: source.to_string(),
+        fs,
+        root,
+    });
+    Ok(())
+}
+
+/// Detach the filesystem mounted on path.
+///
+/// Refused while a file of the filesystem is open or another filesystem
+/// is mounted below it.
+pub fn umount(path: &str) -> Result<(), Error> {
+    let dir = resolve(path, true)?;
+    let in_use = file::is_open_below(&dir.path);
+    let mut mounts = MOUNTS.lock();
+    let index = match mounts.iter().rposition(|m| m.path == dir.path) {
+        Some(index) => index,
+        None => return Err(Error::InvalidArgument),
+    };
+    let nested = mounts[index + 1..]
+        .iter()
+        .any(|m| is_below(&m.path, &dir.path));
+    if nested || in_use {
+        return Err(Error::Busy);
+    }
+    let mount = mounts.remove(index);
+    drop(mounts);
+    mount.fs.sync()
+}
+
+/// Return the list of the mounted filesystems.
+pub fn mounts() -> Vec<MountInfo> {
+    MOUNTS
+        .lock()
+        .iter()
+        .map(|m| MountInfo {
+            path: if m.path.is_empty() {
+                "/".to_string()
+            } else {
+                m.path.clone()
+            },
+            source: m.source.clone(),
+            fstype: m.fs.name(),
+        })
+        .collect()
+}
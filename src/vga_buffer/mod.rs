@@ -1,12 +1,28 @@
 use core::fmt;
+use core::ptr::Unique;
+
+use crate::spinlock::ConsoleLock;
 
 pub mod color;
 mod cursor;
 pub mod writer;
-use self::writer::WRITER;
+use self::writer::{Writer, WRITER};
+
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
 
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+/// Address of the VGA text mode buffer.
+pub const VGA_BUFFER: usize = 0xb8000;
+
+/// Initialise WRITER on the text buffer at the given address.
+pub fn init(buffer: usize) {
+    WRITER.call_once(|| {
+        ConsoleLock::with_name(
+            Writer::new(unsafe { Unique::new_unchecked(buffer as *mut _) }),
+            "WRITER",
+        )
+    });
+}
 
 #[macro_export]
 macro_rules! kprint {
@@ -22,82 +38,66 @@ macro_rules! kprintln {
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    unsafe {
-        // TODO Remove interupt during lock to avoid dead lock.
-        let mut writer_lock = WRITER.lock();
-        writer_lock.cursor_disable();
-        writer_lock.write_fmt(args).unwrap();
-        writer_lock.cursor_update();
-        writer_lock.cursor_enable();
-    }
+    let mut writer_lock = WRITER.lock_irq();
+    writer_lock.cursor_disable();
+    writer_lock.write_fmt(args).unwrap();
+    writer_lock.cursor_update();
+    writer_lock.cursor_enable();
 }
 
 #[macro_export]
 macro_rules! screen_setfgcolor {
     ($fg:expr) => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().set_foreground($fg)
-        }
+        crate::vga_buffer::writer::WRITER
+            .lock_irq()
+            .set_foreground($fg)
     };
 }
 
 #[macro_export]
 macro_rules! screen_setbgcolor {
     ($bg:expr) => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().set_background($bg)
-        }
+        crate::vga_buffer::writer::WRITER
+            .lock_irq()
+            .set_background($bg)
     };
 }
 
 #[macro_export]
 macro_rules! screen_setcolor {
     ($cc:expr) => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().set_color_code($cc)
-        }
+        crate::vga_buffer::writer::WRITER
+            .lock_irq()
+            .set_color_code($cc)
     };
 }
 
 #[macro_export]
 macro_rules! screen_clear {
     () => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().clear()
-        }
+        crate::vga_buffer::writer::WRITER.lock_irq().clear()
     };
 }
 
 #[macro_export]
 macro_rules! screen_next {
     () => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().next_screen()
-        }
+        crate::vga_buffer::writer::WRITER.lock_irq().next_screen()
     };
 }
 
 #[macro_export]
 macro_rules! screen_prev {
     () => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().prev_screen()
-        }
+        crate::vga_buffer::writer::WRITER.lock_irq().prev_screen()
     };
 }
 
 #[macro_export]
 macro_rules! screen_set {
     ($i:expr) => {
-        unsafe {
-            // TODO Remove interupt during lock to avoid dead lock.
-            crate::vga_buffer::writer::WRITER.lock().change_screen(i)
-        }
+        crate::vga_buffer::writer::WRITER
+            .lock_irq()
+            .change_screen($i)
     };
 }
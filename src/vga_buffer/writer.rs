@@ -1,7 +1,7 @@
 use core::fmt;
 use core::ptr::Unique;
 
-use crate::spinlock::Spinlock;
+use crate::{once::Once, spinlock::ConsoleLock};
 
 use super::{
     color::{Color, ColorCode},
@@ -112,11 +112,7 @@ impl Vt {
     }
 }
 
-pub static mut WRITER: Spinlock<Writer> = Spinlock::new(Writer {
-    vt_index: 0,
-    vt: [Vt::new(); VT_NUMBER],
-    buffer: unsafe { Unique::new_unchecked(0xb8000 as *mut _) },
-});
+pub static WRITER: Once<ConsoleLock<Writer>> = Once::with_name("WRITER");
 
 pub const VT_NUMBER: usize = 2;
 